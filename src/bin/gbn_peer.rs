@@ -1,23 +1,12 @@
-#![feature(box_syntax)]
-use std::collections::VecDeque;
-use std::{collections::BTreeMap, sync::Arc};
+use std::sync::Arc;
 
 use clap::Parser;
-use futures::future::Either;
-use tokio::io::AsyncBufReadExt;
-
-use tokio::io::AsyncWriteExt;
-use tokio::io::BufReader;
 use tokio::sync::mpsc;
-use tokio::task;
-use udp_rdt::Args;
 use udp_rdt::{
     fake_udp::UdpSocket,
-    packet::Packet,
-    slide_windows::{
-        gbn::{start_receive_peer, start_send_peer, RecvMsg, SenderMsg},
-        MAX_BUFF_SIZE,
-    },
+    packet::handshake::ArqMode,
+    slide_windows::{config::TransportConfig, transport::run_peer},
+    start_input, start_output, Args,
 };
 
 fn main() {
@@ -33,99 +22,36 @@ fn main() {
 }
 
 async fn task(args: Args) {
-    let mut map = BTreeMap::new();
     let socket = Arc::new(
         UdpSocket::bind(args.local_addr)
             .await
             .expect("Cannot Create Udp socket"),
     );
-
-    let (timeout_rx, mut timeout_tx) = mpsc::channel(16);
-    let (output_rx, mut output_tx) = mpsc::channel::<Vec<u8>>(10);
-
-    let send_msg = start_send_peer(Arc::clone(&socket), args.target_addr, timeout_rx.clone());
-    let recv = start_receive_peer(Arc::clone(&socket), args.target_addr, output_rx.clone());
-    map.insert(args.target_addr, recv);
-
-    task::spawn(async move {
-        let mut stdout = tokio::io::stdout();
-        while let Some(s) = output_tx.recv().await {
-            let s = String::from_utf8_lossy(&s);
-
-            stdout
-                .write_all(format!("\n-----------\nrecv Msg \n{:?} \n-----------\n", s).as_bytes())
-                .await
-                .expect("Write fault");
-        }
-    });
-
-    let input_sender = send_msg.clone();
-    task::spawn(async move {
-        let stdin = BufReader::new(tokio::io::stdin());
-        let mut lines = stdin.lines();
-        let mut strings = VecDeque::new();
-        while let Ok(Some(s)) = lines.next_line().await {
-            let s = s.trim().to_string();
-            println!("input msg :{:?}", s);
-            if !s.is_empty() {
-                strings.push_back(s);
-            } else {
-                while let Some(s) = strings.pop_front() {
-                    input_sender.send(SenderMsg::Msg(s.into_bytes())).await.ok();
-                }
-            }
-        }
-    });
-
-    let mut buf = box [0u8; MAX_BUFF_SIZE];
-    loop {
-        let task = match futures::future::select(
-            Box::pin(socket.recv_from(buf.as_mut())),
-            Box::pin(timeout_tx.recv()),
-        )
-        .await
-        {
-            Either::Left((r, _)) => Either::Left(r),
-            Either::Right((l, _)) => Either::Right(l),
-        };
-
-        match task {
-            Either::Left(r) => {
-                let (size, origin) = r.expect("Udp Socket Fault");
-                let body = &buf[0..size];
-                let packet = Packet::read(body);
-                if origin == args.target_addr {
-                    if let Ok(Some(ref packet)) = packet {
-                        eprintln!("Recv Might ACK packet Ok");
-                        if packet.is_ack() {
-                            eprintln!("Recv ACK {}", packet.get_ack_num());
-                            send_msg
-                                .send(SenderMsg::Ack(packet.get_ack_num()))
-                                .await
-                                .expect("Failure Handle Msg");
-
-                            continue;
-                        }
-                    }
-                }
-                // else
-                // if fault ack packet , recv not reaction
-                // if peer send msg , handle it
-                if let Some(sender) = map.get(&origin) {
-                    // origin socket send previous
-                    sender.send(RecvMsg(packet)).await.ok();
-                } else {
-                    // new origin start recv
-                    let sender = start_receive_peer(Arc::clone(&socket), origin, output_rx.clone());
-                    sender.send(RecvMsg(packet)).await.ok();
-                    map.insert(origin, sender);
-                }
-            }
-            Either::Right(Some(_)) => {
-                eprintln!("Time Out Resend all");
-                send_msg.send(SenderMsg::ResendAll).await.ok();
-            }
-            Either::Right(None) => (),
+    // 接收端只缓存一个 packet，窗口可以用满 8 bit 序号空间
+    let config = TransportConfig {
+        window: 255,
+        ..Default::default()
+    };
+
+    let (input_send, input) = mpsc::channel(64);
+    start_input(input_send, String::into_bytes);
+    let output = start_output();
+
+    // 输入结束后关闭连接退出，对端不可达或重传失败时以错误退出
+    match run_peer(
+        ArqMode::GoBackN,
+        socket,
+        args.target_addr,
+        config,
+        input,
+        output,
+    )
+    .await
+    {
+        Ok(()) => println!("Connection closed"),
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
         }
     }
 }
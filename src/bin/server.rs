@@ -1,11 +1,14 @@
 //! service
 //! recv the message comes form client
 
-use std::{net::Ipv4Addr, time::Duration};
+use std::{net::Ipv4Addr, sync::Arc};
 
+use tokio::sync::mpsc;
 use udp_rdt::{
     fake_udp::UdpSocket,
-    packet::{ack::Ack, Packet},
+    packet::handshake::ArqMode,
+    slide_windows::{config::TransportConfig, transport::run_peer},
+    start_output,
 };
 
 fn main() {
@@ -24,60 +27,23 @@ async fn task() {
 
     println!("UDP Server Started!");
 
-    let mut buf = [0u8; 1024 + 128];
-    let mut write_buf = Vec::with_capacity(1024 + 128);
-    let mut local_id = 0u8;
-    let mut last_ack = Ack::new_ack(u8::MAX);
-
-    while let Ok((size, origin)) = udp_socket.recv_from(&mut buf).await {
-        let local_buf = &buf[0..size];
-        println!("Recv Packet Size : [{size}]");
-        println!("Recv body {:?}", local_buf);
-        let packet = Packet::read(local_buf);
-        if let Ok(Some(packet)) = packet {
-            println!("get Packet {:?}", packet);
-
-            if packet.get_id() == local_id {
-                println!("Packet Verify Pass");
-                // ok
-                // send ack
-                last_ack = Ack::new_ack(local_id);
-                // clear write buf
-                write_buf.clear();
-                let size = last_ack.write(&mut write_buf).expect("send body over flow");
-                let send_body = &write_buf[0..size];
-                udp_socket
-                    .send_to(send_body, origin)
-                    .await
-                    .expect("cannot send ACK");
-
-                // update local id
-                local_id = local_id.wrapping_add(1);
-
-                // show msg
-                let body = packet.get_body();
-                let body = String::from_utf8_lossy(&body);
-                println!("{body}");
-
-                continue;
-            }
-        }
-        println!(
-            "Packet Verify not pass, send last ACK {}",
-            last_ack.get_ack_num()
-        );
-        // error or send same packet again
-        // send last ack
-        write_buf.clear();
-        let size = last_ack.write(&mut write_buf).expect("send buf over flow");
-        let body = &write_buf[0..size];
-        udp_socket
-            .send_to(body, origin)
-            .await
-            .expect("cannot send ACK");
-
-        println!("Something wrong on Packet");
-
-        tokio::time::sleep(Duration::from_secs(2)).await;
+    let client_addr = (Ipv4Addr::from([127, 0, 0, 1]), 5000).into();
+
+    // 服务端只接收消息，输入保持打开，连接不会主动关闭
+    let (_input_send, input) = mpsc::channel(1);
+    let output = start_output();
+
+    if let Err(err) = run_peer(
+        ArqMode::StopAndWait,
+        Arc::new(udp_socket),
+        client_addr,
+        TransportConfig::default(),
+        input,
+        output,
+    )
+    .await
+    {
+        eprintln!("{err}");
+        std::process::exit(1);
     }
 }
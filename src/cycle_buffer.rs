@@ -1,59 +1,101 @@
 use std::ops::DerefMut;
 
-pub struct CycleBuffer<const S: u8, T> {
+use crate::packet::seq::SeqWidth;
+
+/// 以序号为下标的发送缓冲区
+///
+/// 缓冲区最多容纳 `capacity` 个元素，实际存储槽数量为 `capacity` 向上取整的 2 的幂，
+/// 序号通过取低位映射到存储槽，因此 `capacity` 不能超过序号空间
+pub struct CycleBuffer<T> {
     buffer: Vec<BufferWrap<T>>,
-    size: u8,
-    top: u8,
-    button: u8,
+    width: SeqWidth,
+    capacity: u32,
+    size: u32,
+    top: u32,
+    button: u32,
 }
 
-impl<const S: u8, T> CycleBuffer<S, T> {
-    pub fn get_mut(&mut self, buf_id: u8) -> Option<&mut T> {
-        match unsafe { self.buffer.get_unchecked_mut(buf_id as usize) } {
+impl<T> CycleBuffer<T> {
+    fn slot(&self, buf_id: u32) -> usize {
+        buf_id as usize & (self.buffer.len() - 1)
+    }
+
+    /// 序号是否在 `[button, top)` 内
+    fn in_windows(&self, buf_id: u32) -> bool {
+        self.width.in_range(buf_id, self.button, self.size)
+    }
+
+    pub fn get_mut(&mut self, buf_id: u32) -> Option<&mut T> {
+        if !self.in_windows(buf_id) {
+            return None;
+        }
+        let slot = self.slot(buf_id);
+        match unsafe { self.buffer.get_unchecked_mut(slot) } {
             BufferWrap::Data(d, BufferState::Waiting) => Some(d.deref_mut()),
             _ => None,
         }
     }
 
-    pub fn get(&self, buf_id: u8) -> Option<&T> {
-        match unsafe { self.buffer.get_unchecked(buf_id as usize) } {
+    pub fn get(&self, buf_id: u32) -> Option<&T> {
+        if !self.in_windows(buf_id) {
+            return None;
+        }
+        match unsafe { self.buffer.get_unchecked(self.slot(buf_id)) } {
             BufferWrap::Data(d, BufferState::Waiting) => Some(d.as_ref()),
             _ => None,
         }
     }
 
-    pub fn len(&self) -> u8 {
+    pub fn len(&self) -> u32 {
         self.size
     }
-    pub fn button(&self) -> u8 {
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// 缓冲区剩余可用空间
+    pub fn remain(&self) -> u32 {
+        self.capacity - self.size
+    }
+
+    pub fn width(&self) -> SeqWidth {
+        self.width
+    }
+
+    pub fn button(&self) -> u32 {
         self.button
     }
 
-    pub fn top(&self) -> u8 {
+    pub fn top(&self) -> u32 {
         self.top
     }
 
     pub fn push(&mut self, data: T) -> Result<(), CbError> {
-        if self.size == S {
+        if self.size == self.capacity {
             Err(CbError::BufferFilled)?
         }
 
-        unsafe { self.buffer.get_unchecked_mut(self.top as usize) }.update(data);
-        self.top = self.top.wrapping_add(1);
+        let slot = self.slot(self.top);
+        unsafe { self.buffer.get_unchecked_mut(slot) }.update(data);
+        self.top = self.width.add(self.top, 1);
         self.size += 1;
 
         Ok(())
     }
 
-    pub fn buffer_down(&mut self, buf_id: u8) {
-        unsafe { self.buffer.get_unchecked_mut(buf_id as usize) }.set_down();
+    pub fn buffer_down(&mut self, buf_id: u32) {
+        if self.in_windows(buf_id) {
+            let slot = self.slot(buf_id);
+            unsafe { self.buffer.get_unchecked_mut(slot) }.set_down();
+        }
     }
 
     pub fn slide_buff_with_done_data(&mut self) -> Vec<T> {
-        let mut idx = self.button;
         let mut vec = Vec::new();
-        while idx != self.top {
-            let buf = unsafe { self.buffer.get_unchecked_mut(idx as usize) };
+        while self.size > 0 {
+            let slot = self.slot(self.button);
+            let buf = unsafe { self.buffer.get_unchecked_mut(slot) };
             if buf.is_down() {
                 vec.extend(buf.take());
                 self.size -= 1;
@@ -61,17 +103,15 @@ impl<const S: u8, T> CycleBuffer<S, T> {
                 break;
             }
 
-            idx = idx.wrapping_add(1);
-            self.button = idx;
+            self.button = self.width.add(self.button, 1);
         }
         vec
     }
 
     pub fn slide_buff(&mut self) {
-        let mut idx = self.button;
-
-        while idx != self.top {
-            let buf = unsafe { self.buffer.get_unchecked_mut(idx as usize) };
+        while self.size > 0 {
+            let slot = self.slot(self.button);
+            let buf = unsafe { self.buffer.get_unchecked_mut(slot) };
             if buf.is_down() {
                 buf.remove();
                 self.size -= 1;
@@ -79,29 +119,44 @@ impl<const S: u8, T> CycleBuffer<S, T> {
                 break;
             }
 
-            idx = idx.wrapping_add(1);
-            self.button = idx;
+            self.button = self.width.add(self.button, 1);
         }
     }
 
-    pub fn set_button(&mut self, buf_id: u8) -> Result<(), ()> {
+    pub fn set_button(&mut self, buf_id: u32) -> Result<(), CbError> {
         // buf id in windows , update
-        if buf_id.wrapping_sub(self.button) < self.top.wrapping_sub(self.button) {
+        if self.in_windows(buf_id) {
             // 当前buf id 以及之前的均完成了
-            self.size -= buf_id.wrapping_sub(self.button).wrapping_add(1);
-            self.button = buf_id.wrapping_add(1);
+            self.size -= self.width.sub(buf_id, self.button) + 1;
+            self.button = self.width.add(buf_id, 1);
             Ok(())
         } else {
             // do nothing
-            Err(())
+            Err(CbError::OutOfWindows)
         }
     }
+
+    /// 丢弃全部数据，窗口从 `start` 重新开始
+    pub fn reset(&mut self, start: u32) {
+        self.buffer.iter_mut().for_each(BufferWrap::remove);
+        self.size = 0;
+        self.top = start & self.width.mask();
+        self.button = self.top;
+    }
 }
 
-impl<const S: u8, T> CycleBuffer<S, T> {
-    pub fn new() -> Self {
+impl<T> CycleBuffer<T> {
+    pub fn new(capacity: u32, width: SeqWidth) -> Self {
+        assert!(
+            capacity > 0 && capacity <= width.mask(),
+            "buffer size {capacity} out of {width:?} sequence space"
+        );
         Self {
-            buffer: (0..=u8::MAX).map(|_| BufferWrap::Nil).collect(),
+            buffer: (0..capacity.next_power_of_two())
+                .map(|_| BufferWrap::Nil)
+                .collect(),
+            width,
+            capacity,
             size: 0,
             top: 0,
             button: 0,
@@ -141,10 +196,7 @@ impl<T> BufferWrap<T> {
     }
 
     pub fn is_down(&self) -> bool {
-        match self {
-            BufferWrap::Data(_, BufferState::Done) => true,
-            _ => false,
-        }
+        matches!(self, BufferWrap::Data(_, BufferState::Done))
     }
 }
 #[derive(Debug, Default)]
@@ -158,22 +210,26 @@ pub enum BufferState {
 pub enum CbError {
     #[error("缓冲区已满")]
     BufferFilled,
+    #[error("序号不在窗口内")]
+    OutOfWindows,
 }
 
 #[cfg(test)]
 mod test {
-    use crate::cycle_buffer::{BufferState, BufferWrap};
+    use crate::{
+        cycle_buffer::{BufferState, BufferWrap},
+        packet::seq::SeqWidth,
+    };
 
     use super::{CbError, CycleBuffer};
 
     #[test]
     fn test_slide() {
-        let mut buf = CycleBuffer::<16, u8>::new();
-        buf.buffer
-            .iter_mut()
-            .enumerate()
-            .filter(|(idx, _)| idx < &5 || idx >= &245)
-            .for_each(|(_, v)| *v = BufferWrap::Data(Box::new(11), BufferState::Done));
+        let mut buf = CycleBuffer::<u8>::new(16, SeqWidth::U8);
+        (245..=255).chain(0..5).for_each(|idx| {
+            let slot = buf.slot(idx);
+            buf.buffer[slot] = BufferWrap::Data(Box::new(11), BufferState::Done)
+        });
         buf.button = 245;
         buf.top = 5;
         buf.size = 16;
@@ -184,7 +240,7 @@ mod test {
         assert_eq!(resp, Err(CbError::BufferFilled));
 
         //set a buf id out of top and button range , nothing happen
-        buf.set_button(200).ok();
+        assert_eq!(buf.set_button(200), Err(CbError::OutOfWindows));
 
         assert_eq!(buf.button, 245);
         assert_eq!(buf.top, 5);
@@ -219,4 +275,32 @@ mod test {
         assert_eq!(buf.top, 6);
         assert_eq!(buf.size, 0);
     }
+
+    #[test]
+    fn test_wide_seq() {
+        let mut buf = CycleBuffer::<u32>::new(4, SeqWidth::U16);
+        buf.button = u16::MAX as u32 - 1;
+        buf.top = buf.button;
+
+        (0..4).for_each(|v| buf.push(v).unwrap());
+        assert_eq!(buf.top, 2);
+        assert_eq!(buf.push(4), Err(CbError::BufferFilled));
+
+        assert_eq!(buf.get(u16::MAX as u32), Some(&1));
+        assert_eq!(buf.get(1), Some(&3));
+        // same slot but out of windows
+        assert_eq!(buf.get(5), None);
+        buf.buffer_down(5);
+        assert_eq!(buf.get(1), Some(&3));
+
+        // ack over the wrap point
+        assert_eq!(buf.set_button(0), Ok(()));
+        assert_eq!(buf.button, 1);
+        assert_eq!(buf.len(), 1);
+
+        buf.reset(1000);
+        assert!(buf.is_empty());
+        assert_eq!(buf.get(1), None);
+        assert_eq!((buf.button, buf.top), (1000, 1000));
+    }
 }
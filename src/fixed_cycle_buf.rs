@@ -1,28 +1,50 @@
+use crate::packet::seq::SeqWidth;
+
+/// 接收窗口，从 `offset` 开始的 `capacity` 个序号可以写入
 #[derive(Debug)]
-pub struct FixedCycleBuffer<const S: u8, T> {
+pub struct FixedCycleBuffer<T> {
     buffer: Vec<BufferWrap<T>>,
-    offset: u8,
+    width: SeqWidth,
+    capacity: u32,
+    offset: u32,
 }
 
-impl<const S: u8, T> FixedCycleBuffer<S, T> {
-    pub fn new() -> Self {
+impl<T> FixedCycleBuffer<T> {
+    pub fn new(capacity: u32, width: SeqWidth) -> Self {
+        assert!(
+            capacity > 0 && capacity <= width.mask(),
+            "buffer size {capacity} out of {width:?} sequence space"
+        );
         Self {
-            buffer: (0..=u8::MAX).map(|_| BufferWrap::Nil).collect(),
+            buffer: (0..capacity.next_power_of_two())
+                .map(|_| BufferWrap::Nil)
+                .collect(),
+            width,
+            capacity,
             offset: 0,
         }
     }
 
-    pub fn offset(&self) -> u8 {
+    fn slot(&self, packet_id: u32) -> usize {
+        packet_id as usize & (self.buffer.len() - 1)
+    }
+
+    pub fn offset(&self) -> u32 {
         self.offset
     }
 
-    pub fn calculate_offset(&self, packet_id: u8) -> u8 {
-        packet_id.wrapping_sub(self.offset)
+    pub fn width(&self) -> SeqWidth {
+        self.width
     }
 
-    pub fn insert(&mut self, idx: u8, data: T) -> Result<(), T> {
-        if self.calculate_offset(idx) < S {
-            *{ self.buffer.get_mut(idx as usize).unwrap() } = BufferWrap::Set(data);
+    pub fn calculate_offset(&self, packet_id: u32) -> u32 {
+        self.width.sub(packet_id, self.offset)
+    }
+
+    pub fn insert(&mut self, idx: u32, data: T) -> Result<(), T> {
+        if self.calculate_offset(idx) < self.capacity {
+            let slot = self.slot(idx);
+            *{ self.buffer.get_mut(slot).unwrap() } = BufferWrap::Set(data);
             Ok(())
         } else {
             // local not in windows
@@ -30,14 +52,51 @@ impl<const S: u8, T> FixedCycleBuffer<S, T> {
         }
     }
 
+    /// 丢弃全部数据，窗口从 `start` 重新开始
+    pub fn reset(&mut self, start: u32) {
+        self.buffer
+            .iter_mut()
+            .for_each(|buf| *buf = BufferWrap::Nil);
+        self.offset = start & self.width.mask();
+    }
+
+    /// 窗口内尚未收到 packet 的空位数量
+    pub fn remain(&self) -> u32 {
+        let used = (0..self.capacity)
+            .filter(|&distance| {
+                let packet_id = self.width.add(self.offset, distance);
+                self.buffer[self.slot(packet_id)].is_set()
+            })
+            .count() as u32;
+        self.capacity - used
+    }
+
+    /// 窗口内已收到的序号区间，闭区间，按序号先后排列
+    pub fn received_ranges(&self) -> Vec<(u32, u32)> {
+        let mut ranges = Vec::<(u32, u32)>::new();
+        let mut last_set = false;
+        for distance in 0..self.capacity {
+            let packet_id = self.width.add(self.offset, distance);
+            let is_set = self.buffer[self.slot(packet_id)].is_set();
+            match (is_set, last_set, ranges.last_mut()) {
+                (true, true, Some((_, end))) => *end = packet_id,
+                (true, _, _) => ranges.push((packet_id, packet_id)),
+                _ => (),
+            }
+            last_set = is_set;
+        }
+        ranges
+    }
+
     pub fn slide_windows(&mut self) -> Vec<T> {
         let mut vec = Vec::new();
 
         loop {
-            let buf = self.buffer.get_mut(self.offset as usize).unwrap();
+            let slot = self.slot(self.offset);
+            let buf = self.buffer.get_mut(slot).unwrap();
             if buf.is_set() {
                 vec.extend(buf.take());
-                self.offset = self.offset.wrapping_add(1);
+                self.offset = self.width.add(self.offset, 1);
             } else {
                 // hit nil, stop
                 break vec;
@@ -69,18 +128,18 @@ impl<T> BufferWrap<T> {
 
 #[cfg(test)]
 mod test {
+    use crate::packet::seq::SeqWidth;
+
     use super::{BufferWrap, FixedCycleBuffer};
 
     #[test]
     fn test() {
-        let mut buffer = FixedCycleBuffer::<20, u8>::new();
+        let mut buffer = FixedCycleBuffer::<u8>::new(20, SeqWidth::U8);
         buffer.offset = 245;
-        buffer
-            .buffer
-            .iter_mut()
-            .enumerate()
-            .filter(|(idx, _)| (*idx as u8).wrapping_sub(245) < 20)
-            .for_each(|(idx, v)| *v = BufferWrap::Set(idx as u8));
+        (245..=255).chain(0..9).for_each(|idx| {
+            let slot = buffer.slot(idx);
+            buffer.buffer[slot] = BufferWrap::Set(idx as u8)
+        });
 
         // slide window offset change to 9
         let v = buffer.slide_windows();
@@ -115,4 +174,39 @@ mod test {
         assert_eq!(v, [9, 10]);
         assert_eq!(buffer.offset, 11);
     }
+
+    #[test]
+    fn test_received_ranges() {
+        let mut buffer = FixedCycleBuffer::<u8>::new(8, SeqWidth::U8);
+        buffer.offset = 254;
+        assert_eq!(buffer.received_ranges(), []);
+        assert_eq!(buffer.remain(), 8);
+
+        buffer.insert(255, 0).unwrap();
+        buffer.insert(0, 0).unwrap();
+        buffer.insert(3, 0).unwrap();
+        assert_eq!(buffer.received_ranges(), [(255, 0), (3, 3)]);
+        assert_eq!(buffer.remain(), 5);
+    }
+
+    #[test]
+    fn test_wide_seq() {
+        let mut buffer = FixedCycleBuffer::<u32>::new(8, SeqWidth::U32);
+        buffer.offset = u32::MAX - 1;
+
+        // same slot as offset but far out of windows
+        assert_eq!(buffer.insert((u32::MAX - 1).wrapping_add(16), 0), Err(0));
+
+        assert_eq!(buffer.insert(u32::MAX, 1), Ok(()));
+        assert_eq!(buffer.insert(u32::MAX - 1, 0), Ok(()));
+        assert_eq!(buffer.insert(0, 2), Ok(()));
+
+        assert_eq!(buffer.slide_windows(), [0, 1, 2]);
+        assert_eq!(buffer.offset, 1);
+
+        buffer.insert(2, 3).unwrap();
+        buffer.reset(7);
+        assert_eq!(buffer.offset, 7);
+        assert_eq!(buffer.received_ranges(), []);
+    }
 }
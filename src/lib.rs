@@ -40,7 +40,8 @@ pub fn start_output() -> mpsc::Sender<Vec<u8>> {
     output_rx
 }
 
-pub fn start_input<T, F>(sender: mpsc::Sender<T>, handle: F)
+/// 读取标准输入，空行时发送之前的输入，输入结束时返回的任务完成
+pub fn start_input<T, F>(sender: mpsc::Sender<T>, handle: F) -> task::JoinHandle<()>
 where
     T: Send + 'static,
     F: Fn(String) -> T + Send + 'static,
@@ -61,5 +62,8 @@ where
                 }
             }
         }
-    });
+        while let Some(s) = strings.pop_front() {
+            sender.send(handle(s)).await.ok();
+        }
+    })
 }
@@ -1,33 +1,76 @@
+use byteorder::{ByteOrder, BE};
+
 use super::{
     flags::{PackSplit, PacketType},
-    Packet,
+    Packet, PacketRef,
 };
 
 pub type Ack = Packet;
 
+/// 没有通告窗口的 ACK body
+const ACK_BODY: [u8; 1] = [0xFF];
+/// 携带通告窗口的 ACK body 长度，标记后跟随 u32 窗口大小
+const WINDOW_ACK_LEN: usize = 5;
+
+fn is_ack_body(body: &[u8]) -> bool {
+    body == ACK_BODY || (body.len() == WINDOW_ACK_LEN && body[0] == ACK_BODY[0])
+}
+
+fn read_window(body: &[u8]) -> Option<u32> {
+    (body.len() == WINDOW_ACK_LEN && body[0] == ACK_BODY[0]).then(|| BE::read_u32(&body[1..]))
+}
+
 impl Ack {
-    pub fn new_ack(code: u8) -> Self {
-        Ack::new(code, vec![0xFF], PacketType::Ack, PackSplit::End)
+    pub fn new_ack(code: u32) -> Self {
+        Ack::new(code, ACK_BODY.to_vec(), PacketType::Ack, PackSplit::End)
+    }
+
+    /// 携带接收端通告窗口的 ACK，窗口为接收端还能容纳的 packet 数量
+    pub fn new_window_ack(code: u32, window: u32) -> Self {
+        let mut body = vec![0u8; WINDOW_ACK_LEN];
+        body[0] = ACK_BODY[0];
+        BE::write_u32(&mut body[1..], window);
+        Ack::new(code, body, PacketType::Ack, PackSplit::End)
     }
 
     /// 判断是否为ack 以及是否为对应code
-    pub fn is_correct_ack(&self, code: u8) -> bool {
+    pub fn is_correct_ack(&self, code: u32) -> bool {
         self.identify_code == code && self.is_ack()
     }
     pub fn is_ack(&self) -> bool {
-        matches!(self.packet_type, PacketType::Ack) && self.body == [0xFF]
+        matches!(self.packet_type, PacketType::Ack) && is_ack_body(&self.body)
+    }
+
+    pub fn get_ack_num(&self) -> u32 {
+        self.identify_code
+    }
+
+    /// ACK 携带的通告窗口
+    pub fn get_window(&self) -> Option<u32> {
+        self.is_ack().then(|| read_window(&self.body)).flatten()
+    }
+}
+
+impl PacketRef<'_> {
+    pub fn is_ack(&self) -> bool {
+        matches!(self.packet_type, PacketType::Ack) && is_ack_body(self.body)
     }
 
-    pub fn get_ack_num(&self) -> u8 {
+    pub fn get_ack_num(&self) -> u32 {
         self.identify_code
     }
+
+    /// ACK 携带的通告窗口
+    pub fn get_window(&self) -> Option<u32> {
+        self.is_ack().then(|| read_window(self.body)).flatten()
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::packet::{
         flags::{PackSplit, PacketType},
-        Packet,
+        Packet, PacketRef,
     };
 
     use super::Ack;
@@ -53,5 +96,24 @@ mod test {
         // correct ack
         let ack = Ack::new_ack(0);
         assert!(ack.is_correct_ack(0));
+        assert_eq!(ack.get_window(), None);
+    }
+
+    #[test]
+    fn window_ack_test() {
+        let ack = Ack::new_window_ack(7, 300);
+        assert!(ack.is_correct_ack(7));
+        assert_eq!(ack.get_window(), Some(300));
+
+        let mut buf = Vec::new();
+        ack.write(&mut buf).unwrap();
+        let packet = PacketRef::read(&buf).unwrap();
+        assert!(packet.is_ack());
+        assert_eq!(packet.get_ack_num(), 7);
+        assert_eq!(packet.get_window(), Some(300));
+
+        // 零窗口
+        let ack = Ack::new_window_ack(7, 0);
+        assert_eq!(ack.get_window(), Some(0));
     }
 }
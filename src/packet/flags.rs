@@ -3,9 +3,14 @@ use std::{
     ops::{BitOr, BitOrAssign},
 };
 
-use byteorder::ReadBytesExt;
+use byteorder::{ReadBytesExt, BE};
+
+use super::seq::SeqWidth;
+use crate::verify::ChecksumKind;
+
+/// 低 8 bit 为分段、body 长度类型与 packet 类型，高 8 bit 为扩展标志
 #[derive(PartialEq, Eq)]
-pub struct PacketFlag(u8);
+pub struct PacketFlag(u16);
 
 /// Packet split: End
 const END_PACKET: PacketFlag = PacketFlag::new(0b01_000_000); //001
@@ -25,7 +30,7 @@ const U16_SIZE: PacketFlag = PacketFlag::new(0o030);
 const U8_SIZE: PacketFlag = PacketFlag::new(0o040);
 const SINGLE: PacketFlag = PacketFlag::new(0o050);
 const EMPTY: PacketFlag = PacketFlag::new(0o060);
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BodySize {
     U64,
     U32,
@@ -35,11 +40,24 @@ pub enum BodySize {
     Empty,
 }
 
-/// packet type
+/// sequence width
+const SEQ_U8: PacketFlag = PacketFlag::new(0x0000);
+const SEQ_U16: PacketFlag = PacketFlag::new(0x0100);
+const SEQ_U32: PacketFlag = PacketFlag::new(0x0200);
 
+/// checksum kind
+const CHECKSUM_INTERNET: PacketFlag = PacketFlag::new(0x0000);
+const CHECKSUM_CRC32C: PacketFlag = PacketFlag::new(0x0400);
+const CHECKSUM_HASH64: PacketFlag = PacketFlag::new(0x0800);
+
+/// packet type
 const DATA: PacketFlag = PacketFlag::new(0b00_000_001);
 const ACK: PacketFlag = PacketFlag::new(0b00_000_010);
 const LEAVE: PacketFlag = PacketFlag::new(0b00_000_011);
+const SACK: PacketFlag = PacketFlag::new(0b00_000_100);
+const NAK: PacketFlag = PacketFlag::new(0b00_000_101);
+const HANDSHAKE: PacketFlag = PacketFlag::new(0b00_000_110);
+const KEEPALIVE: PacketFlag = PacketFlag::new(0b00_000_111);
 
 #[derive(Debug, Clone, Copy, Default)]
 pub enum PacketType {
@@ -47,18 +65,26 @@ pub enum PacketType {
     Data,
     Ack,
     Leave,
+    Sack,
+    Nak,
+    Handshake,
+    KeepAlive,
 }
 
 impl PacketFlag {
-    const fn new(flag: u8) -> Self {
+    const fn new(flag: u16) -> Self {
         Self(flag)
     }
 
     pub fn from_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
-        Ok(Self::new(reader.read_u8()?))
+        Ok(Self::new(reader.read_u16::<BE>()?))
     }
 
-    pub fn get_flag(&self) -> u8 {
+    pub fn from_bits(flag: u16) -> Self {
+        Self::new(flag)
+    }
+
+    pub fn get_flag(&self) -> u16 {
         self.0
     }
 }
@@ -82,7 +108,13 @@ impl PacketFlag {
         self.0 & rhs.0 > 0
     }
 
-    pub fn from_packet_info(size: usize, split: PackSplit, ty: PacketType) -> Self {
+    pub fn from_packet_info(
+        size: usize,
+        split: PackSplit,
+        ty: PacketType,
+        seq_width: SeqWidth,
+        checksum: ChecksumKind,
+    ) -> Self {
         let size = if size == 0 {
             EMPTY
         } else if size == 1 {
@@ -106,9 +138,25 @@ impl PacketFlag {
             PacketType::Data => DATA,
             PacketType::Ack => ACK,
             PacketType::Leave => LEAVE,
+            PacketType::Sack => SACK,
+            PacketType::Nak => NAK,
+            PacketType::Handshake => HANDSHAKE,
+            PacketType::KeepAlive => KEEPALIVE,
         };
 
-        size | split | ty
+        let seq_width = match seq_width {
+            SeqWidth::U8 => SEQ_U8,
+            SeqWidth::U16 => SEQ_U16,
+            SeqWidth::U32 => SEQ_U32,
+        };
+
+        let checksum = match checksum {
+            ChecksumKind::Internet => CHECKSUM_INTERNET,
+            ChecksumKind::Crc32c => CHECKSUM_CRC32C,
+            ChecksumKind::Hash64 => CHECKSUM_HASH64,
+        };
+
+        size | split | ty | seq_width | checksum
     }
 
     pub fn get_pack_size(&self) -> Option<BodySize> {
@@ -131,11 +179,33 @@ impl PacketFlag {
         }
     }
 
+    pub fn get_seq_width(&self) -> Option<SeqWidth> {
+        match Self(self.0 & 0x0300) {
+            SEQ_U8 => Some(SeqWidth::U8),
+            SEQ_U16 => Some(SeqWidth::U16),
+            SEQ_U32 => Some(SeqWidth::U32),
+            _ => None,
+        }
+    }
+
+    pub fn get_checksum(&self) -> Option<ChecksumKind> {
+        match Self(self.0 & 0x0C00) {
+            CHECKSUM_INTERNET => Some(ChecksumKind::Internet),
+            CHECKSUM_CRC32C => Some(ChecksumKind::Crc32c),
+            CHECKSUM_HASH64 => Some(ChecksumKind::Hash64),
+            _ => None,
+        }
+    }
+
     pub fn get_pack_type(&self) -> Option<PacketType> {
         match Self(self.0 & 0b00_000_111) {
             DATA => Some(PacketType::Data),
             ACK => Some(PacketType::Ack),
             LEAVE => Some(PacketType::Leave),
+            SACK => Some(PacketType::Sack),
+            NAK => Some(PacketType::Nak),
+            HANDSHAKE => Some(PacketType::Handshake),
+            KEEPALIVE => Some(PacketType::KeepAlive),
             _ => None,
         }
     }
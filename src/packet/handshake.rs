@@ -0,0 +1,170 @@
+//! 连接建立握手
+//!
+//! packet code 为发起方的初始序号，body 依次为握手阶段、ARQ 模式（各 1 byte）
+//! 与窗口大小（u32）
+
+use byteorder::{ByteOrder, BE};
+
+use super::{
+    flags::{PackSplit, PacketType},
+    seq::SeqWidth,
+    Packet, PacketRef,
+};
+
+const HANDSHAKE_BODY_SIZE: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeStage {
+    /// 发起方请求建立连接
+    Syn,
+    /// 接收方同意建立连接并给出协商后的窗口
+    SynAck,
+    /// 发起方确认连接建立
+    Ack,
+    /// 接收方没有对应的连接，要求发起方重新握手，code 为被拒绝的 packet 序号
+    Reset,
+    /// 发起方已丢失旧连接，要求接收方结束 code 对应的连接
+    Abort,
+}
+
+impl HandshakeStage {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(Self::Syn),
+            2 => Some(Self::SynAck),
+            3 => Some(Self::Ack),
+            4 => Some(Self::Reset),
+            5 => Some(Self::Abort),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::Syn => 1,
+            Self::SynAck => 2,
+            Self::Ack => 3,
+            Self::Reset => 4,
+            Self::Abort => 5,
+        }
+    }
+}
+
+/// 双方使用的自动重传方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArqMode {
+    GoBackN,
+    SelectResend,
+    StopAndWait,
+}
+
+impl ArqMode {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(Self::GoBackN),
+            2 => Some(Self::SelectResend),
+            3 => Some(Self::StopAndWait),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::GoBackN => 1,
+            Self::SelectResend => 2,
+            Self::StopAndWait => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    pub stage: HandshakeStage,
+    /// 发起方的初始序号
+    pub isn: u32,
+    pub mode: ArqMode,
+    /// SYN 中为发起方期望的窗口，SYN-ACK 中为协商后的窗口
+    pub window: u32,
+}
+
+impl Handshake {
+    fn parse(packet_type: PacketType, isn: u32, body: &[u8]) -> Option<Self> {
+        if !matches!(packet_type, PacketType::Handshake) || body.len() != HANDSHAKE_BODY_SIZE {
+            return None;
+        }
+
+        Some(Self {
+            stage: HandshakeStage::from_byte(body[0])?,
+            isn,
+            mode: ArqMode::from_byte(body[1])?,
+            window: BE::read_u32(&body[2..]),
+        })
+    }
+}
+
+impl Packet {
+    pub fn new_handshake(handshake: &Handshake, seq_width: SeqWidth) -> Self {
+        let mut body = vec![
+            handshake.stage.to_byte(),
+            handshake.mode.to_byte(),
+            0,
+            0,
+            0,
+            0,
+        ];
+        BE::write_u32(&mut body[2..], handshake.window);
+
+        Packet::new(handshake.isn, body, PacketType::Handshake, PackSplit::End)
+            .with_seq_width(seq_width)
+    }
+
+    /// 解析握手，body 格式不正确时返回 None
+    pub fn as_handshake(&self) -> Option<Handshake> {
+        Handshake::parse(self.packet_type, self.identify_code, &self.body)
+    }
+}
+
+impl PacketRef<'_> {
+    pub fn is_handshake(&self) -> bool {
+        matches!(self.packet_type, PacketType::Handshake)
+    }
+
+    /// 解析握手，body 格式不正确时返回 None
+    pub fn as_handshake(&self) -> Option<Handshake> {
+        Handshake::parse(self.packet_type, self.identify_code, self.body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::packet::{seq::SeqWidth, Packet, PacketRef};
+
+    use super::{ArqMode, Handshake, HandshakeStage};
+
+    #[test]
+    fn test_handshake() {
+        let syn = Handshake {
+            stage: HandshakeStage::Syn,
+            isn: 40000,
+            mode: ArqMode::SelectResend,
+            window: 128,
+        };
+        let packet = Packet::new_handshake(&syn, SeqWidth::U16);
+        assert_eq!(packet.as_handshake(), Some(syn.clone()));
+
+        let mut buf = Vec::new();
+        packet.write(&mut buf).unwrap();
+        let packet = PacketRef::read(&buf).unwrap();
+        assert!(packet.is_handshake());
+        assert!(!packet.is_data());
+        assert_eq!(packet.as_handshake(), Some(syn));
+
+        // unknown stage
+        let mut packet = packet.to_owned();
+        packet.body[0] = 0;
+        assert_eq!(packet.as_handshake(), None);
+
+        // data packet is not handshake
+        assert_eq!(Packet::new_data(0, vec![0; 6]).as_handshake(), None);
+    }
+}
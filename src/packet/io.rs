@@ -1,27 +1,43 @@
-use std::io::{self, copy, Cursor, Read, Write};
+use std::io::{self, copy, Cursor, Write};
 
-use byteorder::{ReadBytesExt, WriteBytesExt, BE};
-
-use crate::verify::{verify, verify_info_gen};
+use byteorder::{ByteOrder, WriteBytesExt, BE};
 
 use super::{
     flags::{BodySize, PacketFlag},
-    Packet,
+    Packet, PacketDecodeError, PacketRef, PROTOCOL_MAGIC, PROTOCOL_VERSION, SUPPORTED_VERSIONS,
 };
 
 impl Packet {
     pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
-        let flag =
-            PacketFlag::from_packet_info(self.body.len(), self.packet_split, self.packet_type);
+        if self.identify_code > self.seq_width.mask() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "packet code {} out of {:?} sequence space",
+                    self.identify_code, self.seq_width
+                ),
+            ));
+        }
+        let flag = PacketFlag::from_packet_info(
+            self.body.len(),
+            self.packet_split,
+            self.packet_type,
+            self.seq_width,
+            self.checksum,
+        );
         let body = &mut self.body.as_slice();
         let buf_writer = {
-            let mut writer = Vec::with_capacity(1 + self.body.len() + 2);
+            let mut writer =
+                Vec::with_capacity(3 + 2 + self.seq_width.bytes() + self.body.len() + 8);
 
             // generate send body
+            // protocol magic & version
+            writer.write_u16::<BE>(PROTOCOL_MAGIC)?;
+            writer.write_u8(PROTOCOL_VERSION)?;
             // body flag
-            writer.write_u8(flag.get_flag())?;
+            writer.write_u16::<BE>(flag.get_flag())?;
             // body code
-            writer.write_u8(self.identify_code)?;
+            writer.write_uint::<BE>(self.identify_code as u64, self.seq_width.bytes())?;
             // body size
 
             let _ = Self::write_body_size(body.len(), flag, &mut writer)?;
@@ -31,49 +47,19 @@ impl Packet {
             writer
         };
         // generate verify code
-        let verify = verify_info_gen(&buf_writer);
+        let verify = self.checksum.generate(&buf_writer);
 
         // write body
         let mut buf_writer = Cursor::new(buf_writer);
         let size = copy(&mut buf_writer, writer)?;
         // write verify
-        writer.write_u16::<BE>(verify)?;
+        writer.write_uint::<BE>(verify, self.checksum.size())?;
 
-        Ok(2 + size as usize)
+        Ok(self.checksum.size() + size as usize)
     }
 
-    pub fn read(entity: &[u8]) -> io::Result<Option<Self>> {
-        if !verify(entity) {
-            println!("Verify Failure");
-            Ok(None)
-        } else {
-            let mut reader = entity;
-            // pack flag
-            let flag = PacketFlag::from_reader(&mut reader)?;
-
-            // packet code
-            let code = reader.read_u8()?;
-            // packet size
-            let size = Self::read_body_size(&flag, &mut reader)?;
-
-            let ty = flag.get_pack_type();
-            let split = flag.get_pack_split();
-
-            // body
-            let body = size
-                .map(|size| {
-                    let mut buf = vec![0u8; size];
-                    reader.read_exact(&mut buf).map(|_| buf)
-                })
-                .transpose()?;
-
-            // verify info
-            let _ = reader.read_u16::<BE>()?;
-
-            let task = || Some(Self::new(code, body?, ty?, split?));
-
-            Ok(task())
-        }
+    pub fn read(entity: &[u8]) -> Result<Self, PacketDecodeError> {
+        PacketRef::read(entity).map(|packet| packet.to_owned())
     }
 
     fn write_body_size<W: Write>(
@@ -102,29 +88,136 @@ impl Packet {
         }
     }
 
-    fn read_body_size<R: Read>(flag: &PacketFlag, reader: &mut R) -> io::Result<Option<usize>> {
-        flag.get_pack_size()
-            .map(|size| match size {
-                BodySize::U64 => reader.read_u64::<BE>().map(|v| v as _),
-                BodySize::U32 => reader.read_u32::<BE>().map(|v| v as _),
-                BodySize::U16 => reader.read_u16::<BE>().map(|v| v as _),
-                BodySize::U8 => reader.read_u8().map(|v| v as _),
-                BodySize::Single => Ok(1usize),
-                BodySize::Empty => Ok(0),
-            })
-            .transpose()
+    fn read_body_size(flag: &PacketFlag, reader: &mut &[u8]) -> Result<usize, PacketDecodeError> {
+        let class = flag
+            .get_pack_size()
+            .ok_or(PacketDecodeError::UnknownSizeClass(flag.get_flag()))?;
+
+        let (size, min) = match class {
+            BodySize::U64 => (BE::read_u64(take(reader, 8)?), u32::MAX as u64 + 1),
+            BodySize::U32 => (BE::read_u32(take(reader, 4)?) as u64, u16::MAX as u64 + 1),
+            BodySize::U16 => (BE::read_u16(take(reader, 2)?) as u64, u8::MAX as u64 + 1),
+            BodySize::U8 => (take(reader, 1)?[0] as u64, 2),
+            BodySize::Single => return Ok(1),
+            BodySize::Empty => return Ok(0),
+        };
+
+        // 长度必须使用能容纳它的最小长度类型
+        if size < min {
+            Err(PacketDecodeError::LengthMismatch { class, len: size })?
+        }
+        usize::try_from(size).map_err(|_| PacketDecodeError::LengthMismatch { class, len: size })
+    }
+}
+
+impl<'a> PacketRef<'a> {
+    /// 校验并解码 packet，body 借用 `entity`
+    ///
+    /// 校验算法由 flag 决定，因此头部在校验之前解析，
+    /// 头部损坏时可能得到 [`PacketDecodeError::BadChecksum`] 以外的错误
+    pub fn read(entity: &'a [u8]) -> Result<Self, PacketDecodeError> {
+        let mut reader = entity;
+
+        // protocol magic & version
+        let magic = BE::read_u16(take(&mut reader, 2)?);
+        if magic != PROTOCOL_MAGIC {
+            Err(PacketDecodeError::BadMagic(magic))?
+        }
+        let version = take(&mut reader, 1)?[0];
+        if !SUPPORTED_VERSIONS.contains(&version) {
+            Err(PacketDecodeError::UnsupportedVersion(version))?
+        }
+
+        // pack flag
+        let flag = PacketFlag::from_bits(BE::read_u16(take(&mut reader, 2)?));
+
+        // verify info
+        let checksum = flag
+            .get_checksum()
+            .ok_or(PacketDecodeError::UnknownChecksum(flag.get_flag()))?;
+        if reader.len() < checksum.size() {
+            Err(PacketDecodeError::TruncatedBody {
+                expect: checksum.size(),
+                remain: reader.len(),
+            })?
+        }
+        if !checksum.check(entity) {
+            Err(PacketDecodeError::BadChecksum)?
+        }
+        // 去掉末尾 verify info
+        reader = &reader[..reader.len() - checksum.size()];
+
+        let ty = flag
+            .get_pack_type()
+            .ok_or(PacketDecodeError::UnknownType(flag.get_flag()))?;
+        let split = flag
+            .get_pack_split()
+            .ok_or(PacketDecodeError::UnknownSplit(flag.get_flag()))?;
+
+        let seq_width = flag
+            .get_seq_width()
+            .ok_or(PacketDecodeError::UnknownSeqWidth(flag.get_flag()))?;
+
+        // packet code
+        let code = BE::read_uint(take(&mut reader, seq_width.bytes())?, seq_width.bytes()) as u32;
+        // packet size
+        let size = Packet::read_body_size(&flag, &mut reader)?;
+        // body
+        let body = take(&mut reader, size)?;
+
+        if !reader.is_empty() {
+            Err(PacketDecodeError::TrailingBytes(reader.len()))?
+        }
+
+        Ok(Self {
+            packet_type: ty,
+            packet_split: split,
+            version,
+            identify_code: code,
+            seq_width,
+            checksum,
+            body,
+        })
+    }
+}
+
+/// 从 reader 头部取出 `size` byte
+fn take<'a>(reader: &mut &'a [u8], size: usize) -> Result<&'a [u8], PacketDecodeError> {
+    if reader.len() < size {
+        Err(PacketDecodeError::TruncatedBody {
+            expect: size,
+            remain: reader.len(),
+        })?
     }
+    let (head, tail) = reader.split_at(size);
+    *reader = tail;
+    Ok(head)
 }
 
 #[cfg(test)]
 mod test {
-    use crate::packet::{flags::PacketFlag, Packet};
+    use byteorder::{WriteBytesExt, BE};
+
+    use crate::{
+        packet::{
+            flags::{BodySize, PacketFlag},
+            seq::SeqWidth,
+            Packet, PacketDecodeError, PacketRef, PROTOCOL_VERSION,
+        },
+        verify::{verify_info_gen, ChecksumKind},
+    };
 
     #[test]
     fn test_write_body_size() {
         let mut buf = Vec::new();
 
-        let flag = PacketFlag::from_packet_info(3, Default::default(), Default::default());
+        let flag = PacketFlag::from_packet_info(
+            3,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        );
 
         Packet::write_body_size(3, flag, &mut buf).unwrap();
 
@@ -143,6 +236,174 @@ mod test {
 
         let resp = Packet::read(&buf);
 
-        println!("{resp:?}")
+        println!("{resp:?}");
+        assert!(resp.is_ok());
+    }
+
+    #[test]
+    fn test_seq_width() {
+        for (width, code) in [
+            (SeqWidth::U8, 0xAB),
+            (SeqWidth::U16, 0xABCD),
+            (SeqWidth::U32, 0xABCD_EF01),
+        ] {
+            let packet = Packet::new_data(code, vec![1, 2]).with_seq_width(width);
+            let mut buf = Vec::new();
+            let size = packet.write(&mut buf).unwrap();
+            assert_eq!(size, 3 + 2 + width.bytes() + 1 + 2 + 2);
+
+            let packet = Packet::read(&buf).unwrap();
+            assert_eq!(packet.get_id(), code);
+            assert_eq!(packet.seq_width(), width);
+        }
+
+        // code out of sequence space
+        let packet = Packet::new_data(256, vec![]);
+        assert!(packet.write(&mut Vec::new()).is_err());
+
+        // unknown width
+        let resp = Packet::read(&with_verify(&[0x03, 0b01_110_001, 0]));
+        assert_eq!(
+            resp.unwrap_err(),
+            PacketDecodeError::UnknownSeqWidth(0x03_71)
+        );
+    }
+
+    #[test]
+    fn test_checksum_kind() {
+        for kind in [
+            ChecksumKind::Internet,
+            ChecksumKind::Crc32c,
+            ChecksumKind::Hash64,
+        ] {
+            let packet = Packet::new_data(1, vec![1, 2, 3, 4]).with_checksum(kind);
+            let mut buf = Vec::new();
+            let size = packet.write(&mut buf).unwrap();
+            assert_eq!(size, 3 + 2 + 1 + 1 + 4 + kind.size());
+
+            let view = PacketRef::read(&buf).unwrap();
+            assert_eq!(view.checksum(), kind);
+            assert_eq!(view.body(), [1, 2, 3, 4]);
+
+            // swap two 16 bit word in body
+            buf.swap(7, 9);
+            buf.swap(8, 10);
+            let resp = PacketRef::read(&buf);
+            match kind {
+                ChecksumKind::Internet => assert!(resp.is_ok()),
+                _ => assert_eq!(resp.unwrap_err(), PacketDecodeError::BadChecksum),
+            }
+        }
+
+        // unknown checksum
+        let resp = Packet::read(&with_verify(&[0x0C, 0b01_110_001, 0]));
+        assert_eq!(
+            resp.unwrap_err(),
+            PacketDecodeError::UnknownChecksum(0x0C_71)
+        );
+    }
+
+    #[test]
+    fn test_packet_ref() {
+        let packet = Packet::new_data(7, vec![1, 2, 3]);
+        let mut buf = Vec::new();
+        packet.write(&mut buf).unwrap();
+
+        let view = PacketRef::read(&buf).unwrap();
+        assert_eq!(view.get_id(), 7);
+        assert!(view.is_data());
+        // body borrow from recv buffer
+        assert_eq!(view.body(), [1, 2, 3]);
+        assert!(std::ptr::eq(view.body(), &buf[7..10]));
+
+        let owned = view.to_owned();
+        assert_eq!(owned.get_id(), 7);
+        assert_eq!(owned.get_body(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_decode_error() {
+        let packet = Packet::new_data(0, vec![1, 1, 1]);
+        let mut buf = Vec::new();
+        packet.write(&mut buf).unwrap();
+
+        // bad checksum
+        let mut bad = buf.clone();
+        bad[8] ^= 0xFF;
+        assert_eq!(
+            Packet::read(&bad).unwrap_err(),
+            PacketDecodeError::BadChecksum
+        );
+
+        // body shorter than size
+        let resp = Packet::read(&with_verify(&[0, 0b01_100_001, 0, 3, 1]));
+        assert_eq!(
+            resp.unwrap_err(),
+            PacketDecodeError::TruncatedBody {
+                expect: 3,
+                remain: 1
+            }
+        );
+
+        // more data than size
+        let resp = Packet::read(&with_verify(&[0, 0b01_100_001, 0, 2, 1, 1, 1]));
+        assert_eq!(resp.unwrap_err(), PacketDecodeError::TrailingBytes(1));
+
+        // unknown type
+        let resp = Packet::read(&with_verify(&[0, 0b01_110_000, 0]));
+        assert_eq!(
+            resp.unwrap_err(),
+            PacketDecodeError::UnknownType(0b01_110_000)
+        );
+
+        // unknown size class
+        let resp = Packet::read(&with_verify(&[0, 0b01_111_001, 0]));
+        assert_eq!(
+            resp.unwrap_err(),
+            PacketDecodeError::UnknownSizeClass(0b01_111_001)
+        );
+
+        // size 1 must be Single
+        let resp = Packet::read(&with_verify(&[0, 0b01_100_001, 0, 1, 1]));
+        assert_eq!(
+            resp.unwrap_err(),
+            PacketDecodeError::LengthMismatch {
+                class: BodySize::U8,
+                len: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_magic_version() {
+        let packet = Packet::new_data(0, vec![1]);
+        let mut buf = Vec::new();
+        packet.write(&mut buf).unwrap();
+        assert_eq!(buf[..3], [0x52, 0x54, PROTOCOL_VERSION]);
+        assert_eq!(PacketRef::read(&buf).unwrap().version(), PROTOCOL_VERSION);
+
+        // stray datagram with right checksum
+        let mut stray = b"hello".to_vec();
+        stray.write_u16::<BE>(verify_info_gen(&stray)).unwrap();
+        assert_eq!(
+            Packet::read(&stray).unwrap_err(),
+            PacketDecodeError::BadMagic(u16::from_be_bytes([b'h', b'e']))
+        );
+
+        // version from future
+        let mut future = vec![0x52, 0x54, PROTOCOL_VERSION + 1, 0, 0b01_110_001, 0];
+        future.write_u16::<BE>(verify_info_gen(&future)).unwrap();
+        assert_eq!(
+            Packet::read(&future).unwrap_err(),
+            PacketDecodeError::UnsupportedVersion(PROTOCOL_VERSION + 1)
+        );
+    }
+
+    /// 加上协议头与校验码
+    fn with_verify(data: &[u8]) -> Vec<u8> {
+        let mut data = [&[0x52, 0x54, PROTOCOL_VERSION], data].concat();
+        let verify = verify_info_gen(&data);
+        data.write_u16::<BE>(verify).unwrap();
+        data
     }
 }
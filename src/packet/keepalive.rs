@@ -0,0 +1,69 @@
+//! 保活探测
+//!
+//! body 为 1 byte 的 Ping / Pong 标记，Pong 的 packet code 与对应的 Ping 相同
+
+use super::{
+    flags::{PackSplit, PacketType},
+    Packet, PacketRef,
+};
+
+const PING: u8 = 1;
+const PONG: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAlive {
+    Ping,
+    Pong,
+}
+
+impl KeepAlive {
+    fn parse(packet_type: PacketType, body: &[u8]) -> Option<Self> {
+        match (packet_type, body) {
+            (PacketType::KeepAlive, [PING]) => Some(Self::Ping),
+            (PacketType::KeepAlive, [PONG]) => Some(Self::Pong),
+            _ => None,
+        }
+    }
+}
+
+impl Packet {
+    pub fn new_ping(code: u32) -> Self {
+        Packet::new(code, vec![PING], PacketType::KeepAlive, PackSplit::End)
+    }
+
+    pub fn new_pong(code: u32) -> Self {
+        Packet::new(code, vec![PONG], PacketType::KeepAlive, PackSplit::End)
+    }
+
+    pub fn as_keepalive(&self) -> Option<KeepAlive> {
+        KeepAlive::parse(self.packet_type, &self.body)
+    }
+}
+
+impl PacketRef<'_> {
+    pub fn as_keepalive(&self) -> Option<KeepAlive> {
+        KeepAlive::parse(self.packet_type, self.body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::packet::{Packet, PacketRef};
+
+    use super::KeepAlive;
+
+    #[test]
+    fn test_keepalive() {
+        let mut buf = Vec::new();
+        Packet::new_ping(3).write(&mut buf).unwrap();
+        let ping = PacketRef::read(&buf).unwrap();
+        assert_eq!(ping.as_keepalive(), Some(KeepAlive::Ping));
+        assert!(!ping.is_data());
+
+        let pong = Packet::new_pong(ping.get_id());
+        assert_eq!(pong.as_keepalive(), Some(KeepAlive::Pong));
+        assert_eq!(pong.get_id(), 3);
+
+        assert_eq!(Packet::new_data(0, vec![2]).as_keepalive(), None);
+    }
+}
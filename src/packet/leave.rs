@@ -0,0 +1,48 @@
+//! 关闭连接（Leave）
+//!
+//! packet code 为发送端下一个待发送的序号，接收端已按序收到该序号之前的全部 packet 后
+//! 以同一序号的 ACK 确认，并结束该连接
+
+use super::{
+    flags::{PackSplit, PacketType},
+    Packet, PacketRef,
+};
+
+pub type Leave = Packet;
+
+impl Leave {
+    pub fn new_leave(code: u32) -> Self {
+        Leave::new(code, Vec::new(), PacketType::Leave, PackSplit::End)
+    }
+
+    pub fn is_leave(&self) -> bool {
+        matches!(self.packet_type, PacketType::Leave) && self.body.is_empty()
+    }
+}
+
+impl PacketRef<'_> {
+    pub fn is_leave(&self) -> bool {
+        matches!(self.packet_type, PacketType::Leave) && self.body.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::packet::PacketRef;
+
+    use super::Leave;
+
+    #[test]
+    fn test_leave() {
+        let leave = Leave::new_leave(17);
+        assert!(leave.is_leave());
+        assert!(!leave.is_data());
+
+        let mut buf = Vec::new();
+        let size = leave.write(&mut buf).unwrap();
+        let packet = PacketRef::read(&buf[..size]).unwrap();
+        assert!(packet.is_leave());
+        assert!(!packet.is_nak());
+        assert_eq!(packet.get_id(), 17);
+    }
+}
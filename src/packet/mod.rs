@@ -1,8 +1,52 @@
 pub mod ack;
 pub mod flags;
+pub mod handshake;
 mod io;
+pub mod keepalive;
+pub mod leave;
+pub mod nak;
+pub mod sack;
+pub mod seq;
 
-use self::flags::{PackSplit, PacketType};
+use self::{
+    flags::{BodySize, PackSplit, PacketType},
+    seq::SeqWidth,
+};
+use crate::verify::ChecksumKind;
+
+/// 协议标识，位于每个 packet 的开头
+pub const PROTOCOL_MAGIC: u16 = u16::from_be_bytes(*b"RT");
+/// 当前写出的协议版本
+pub const PROTOCOL_VERSION: u8 = 1;
+/// 可以解码的协议版本
+pub const SUPPORTED_VERSIONS: &[u8] = &[1];
+
+/// Packet 解码失败的原因
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum PacketDecodeError {
+    #[error("Packet 校验错误")]
+    BadChecksum,
+    #[error("不是本协议的 Packet，magic {0:#06x}")]
+    BadMagic(u16),
+    #[error("不支持的协议版本 {0}")]
+    UnsupportedVersion(u8),
+    #[error("未知的 Packet 类型 flag {0:#018b}")]
+    UnknownType(u16),
+    #[error("未知的 Packet 分段 flag {0:#018b}")]
+    UnknownSplit(u16),
+    #[error("未知的 body 长度类型 flag {0:#018b}")]
+    UnknownSizeClass(u16),
+    #[error("未知的序号宽度 flag {0:#018b}")]
+    UnknownSeqWidth(u16),
+    #[error("未知的校验算法 flag {0:#018b}")]
+    UnknownChecksum(u16),
+    #[error("Packet 被截断，需要 {expect} byte 但只剩余 {remain} byte")]
+    TruncatedBody { expect: usize, remain: usize },
+    #[error("Packet 尾部存在 {0} byte 多余数据")]
+    TrailingBytes(usize),
+    #[error("body 长度 {len} 与长度类型 {class:?} 不匹配")]
+    LengthMismatch { class: BodySize, len: u64 },
+}
 
 #[derive(Debug, Default)]
 pub struct Packet {
@@ -11,32 +55,56 @@ pub struct Packet {
     packet_type: PacketType,
     packet_split: PackSplit,
     /// 当前packet 的编号
-    /// 0 ~ 256
-    identify_code: u8,
+    /// 0 ~ seq_width.mask()
+    identify_code: u32,
+    /// 编号写入时使用的宽度
+    seq_width: SeqWidth,
+    /// 差错校验算法
+    checksum: ChecksumKind,
     /// body ,assume the body size <= 512
     body: Vec<u8>,
 }
 
 impl Packet {
-    pub fn new(code: u8, body: Vec<u8>, ty: PacketType, split: PackSplit) -> Self {
+    pub fn new(code: u32, body: Vec<u8>, ty: PacketType, split: PackSplit) -> Self {
         Self {
             identify_code: code,
+            seq_width: SeqWidth::default(),
+            checksum: ChecksumKind::default(),
             body,
             packet_type: ty,
             packet_split: split,
         }
     }
 
-    pub fn new_data(code: u8, body: Vec<u8>) -> Self {
+    /// 设置编号宽度，编号必须能够被该宽度容纳
+    pub fn with_seq_width(mut self, seq_width: SeqWidth) -> Self {
+        self.seq_width = seq_width;
+        self
+    }
+
+    /// 设置差错校验算法
+    pub fn with_checksum(mut self, checksum: ChecksumKind) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    pub fn new_data(code: u32, body: Vec<u8>) -> Self {
         Self::new(code, body, PacketType::Data, PackSplit::End)
     }
 
     pub fn get_body(self) -> Vec<u8> {
         self.body
     }
-    pub fn get_id(&self) -> u8 {
+    pub fn get_id(&self) -> u32 {
         self.identify_code
     }
+    pub fn seq_width(&self) -> SeqWidth {
+        self.seq_width
+    }
+    pub fn checksum(&self) -> ChecksumKind {
+        self.checksum
+    }
     pub fn is_data(&self) -> bool {
         matches!(self.packet_type, PacketType::Data)
     }
@@ -45,3 +113,56 @@ impl Packet {
         self.packet_split
     }
 }
+
+/// 借用接收缓冲区的 packet 视图
+///
+/// 解码时完成头部与校验码检查，body 直接引用原始数据，
+/// 需要跨任务传递时再通过 [`PacketRef::to_owned`] 转换为 [`Packet`]
+#[derive(Debug, Clone, Copy)]
+pub struct PacketRef<'a> {
+    version: u8,
+    packet_type: PacketType,
+    packet_split: PackSplit,
+    identify_code: u32,
+    seq_width: SeqWidth,
+    checksum: ChecksumKind,
+    body: &'a [u8],
+}
+
+impl<'a> PacketRef<'a> {
+    pub fn body(&self) -> &'a [u8] {
+        self.body
+    }
+    /// 对端写出该 packet 时使用的协议版本
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+    pub fn get_id(&self) -> u32 {
+        self.identify_code
+    }
+    pub fn seq_width(&self) -> SeqWidth {
+        self.seq_width
+    }
+    pub fn checksum(&self) -> ChecksumKind {
+        self.checksum
+    }
+    pub fn is_data(&self) -> bool {
+        matches!(self.packet_type, PacketType::Data)
+    }
+
+    pub fn packet_split(&self) -> PackSplit {
+        self.packet_split
+    }
+
+    /// 复制 body，生成独立的 [`Packet`]
+    pub fn to_owned(&self) -> Packet {
+        Packet::new(
+            self.identify_code,
+            self.body.to_vec(),
+            self.packet_type,
+            self.packet_split,
+        )
+        .with_seq_width(self.seq_width)
+        .with_checksum(self.checksum)
+    }
+}
@@ -0,0 +1,56 @@
+//! 否定确认（NAK）
+//!
+//! packet code 为接收端发现缺失或损坏的序号，发送端收到后立即重传，不必等待超时
+
+use super::{
+    flags::{PackSplit, PacketType},
+    Packet, PacketRef,
+};
+
+pub type Nak = Packet;
+
+impl Nak {
+    pub fn new_nak(code: u32) -> Self {
+        Nak::new(code, Vec::new(), PacketType::Nak, PackSplit::End)
+    }
+
+    pub fn is_nak(&self) -> bool {
+        matches!(self.packet_type, PacketType::Nak) && self.body.is_empty()
+    }
+
+    pub fn get_nak_num(&self) -> u32 {
+        self.identify_code
+    }
+}
+
+impl PacketRef<'_> {
+    pub fn is_nak(&self) -> bool {
+        matches!(self.packet_type, PacketType::Nak) && self.body.is_empty()
+    }
+
+    pub fn get_nak_num(&self) -> u32 {
+        self.identify_code
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::packet::{seq::SeqWidth, PacketRef};
+
+    use super::Nak;
+
+    #[test]
+    fn test_nak() {
+        let nak = Nak::new_nak(300).with_seq_width(SeqWidth::U16);
+        assert!(nak.is_nak());
+        assert!(!nak.is_ack());
+
+        let mut buf = Vec::new();
+        let size = nak.write(&mut buf).unwrap();
+        let packet = PacketRef::read(&buf[..size]).unwrap();
+        assert!(packet.is_nak());
+        assert!(!packet.is_ack());
+        assert!(!packet.is_sack());
+        assert_eq!(packet.get_nak_num(), 300);
+    }
+}
@@ -0,0 +1,123 @@
+//! 选择确认（SACK）
+//!
+//! packet code 为累计确认号，body 先写入 u32 的通告窗口，
+//! 再依次写入若干个已收到的序号区间 `[start, end]`，
+//! 每个序号使用与 packet code 相同的宽度
+
+use byteorder::{ByteOrder, BE};
+
+use super::{
+    flags::{PackSplit, PacketType},
+    seq::SeqWidth,
+    Packet, PacketRef,
+};
+
+/// 单个 SACK 最多携带的区间数量
+pub const MAX_SACK_RANGES: usize = 32;
+/// 通告窗口占用的长度
+const WINDOW_LEN: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sack {
+    /// 累计确认，该序号及之前的 packet 均已收到
+    pub cumulative: u32,
+    /// 累计确认之后已收到的序号区间，闭区间
+    pub ranges: Vec<(u32, u32)>,
+    /// 接收端通告的窗口，还能容纳的 packet 数量
+    pub window: u32,
+}
+
+impl Packet {
+    pub fn new_sack(sack: &Sack, seq_width: SeqWidth) -> Self {
+        let size = seq_width.bytes();
+        let mut body = vec![0u8; WINDOW_LEN + sack.ranges.len().min(MAX_SACK_RANGES) * size * 2];
+        let (window, ranges) = body.split_at_mut(WINDOW_LEN);
+        BE::write_u32(window, sack.window);
+        ranges
+            .chunks_exact_mut(size * 2)
+            .zip(&sack.ranges)
+            .for_each(|(chunk, (start, end))| {
+                let (s, e) = chunk.split_at_mut(size);
+                BE::write_uint(s, *start as u64, size);
+                BE::write_uint(e, *end as u64, size);
+            });
+
+        Packet::new(sack.cumulative, body, PacketType::Sack, PackSplit::End)
+            .with_seq_width(seq_width)
+    }
+}
+
+impl PacketRef<'_> {
+    pub fn is_sack(&self) -> bool {
+        matches!(self.packet_type, PacketType::Sack)
+    }
+
+    /// 解析 SACK，body 缺少通告窗口或不是完整的区间列表时返回 None
+    pub fn as_sack(&self) -> Option<Sack> {
+        let size = self.seq_width.bytes();
+        if !self.is_sack()
+            || self.body.len() < WINDOW_LEN
+            || (self.body.len() - WINDOW_LEN) % (size * 2) != 0
+        {
+            return None;
+        }
+
+        let (window, ranges) = self.body.split_at(WINDOW_LEN);
+        let ranges = ranges
+            .chunks_exact(size * 2)
+            .take(MAX_SACK_RANGES)
+            .map(|chunk| {
+                let (s, e) = chunk.split_at(size);
+                (BE::read_uint(s, size) as u32, BE::read_uint(e, size) as u32)
+            })
+            .collect();
+
+        Some(Sack {
+            cumulative: self.identify_code,
+            ranges,
+            window: BE::read_u32(window),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::packet::{seq::SeqWidth, Packet, PacketRef};
+
+    use super::Sack;
+
+    #[test]
+    fn test_sack() {
+        let sack = Sack {
+            cumulative: 65535,
+            ranges: vec![(2, 4), (7, 7)],
+            window: 12,
+        };
+        let mut buf = Vec::new();
+        Packet::new_sack(&sack, SeqWidth::U16)
+            .write(&mut buf)
+            .unwrap();
+
+        let packet = PacketRef::read(&buf).unwrap();
+        assert!(packet.is_sack());
+        assert!(!packet.is_ack());
+        assert_eq!(packet.as_sack(), Some(sack));
+
+        // 没有区间的 SACK 仍然携带窗口
+        let sack = Sack {
+            cumulative: 3,
+            ranges: Vec::new(),
+            window: 0,
+        };
+        let mut buf = Vec::new();
+        Packet::new_sack(&sack, SeqWidth::U8)
+            .write(&mut buf)
+            .unwrap();
+        assert_eq!(PacketRef::read(&buf).unwrap().as_sack(), Some(sack));
+
+        // data packet is not sack
+        let mut buf = Vec::new();
+        Packet::new_data(0, vec![0; 4]).write(&mut buf).unwrap();
+        assert_eq!(PacketRef::read(&buf).unwrap().as_sack(), None);
+    }
+}
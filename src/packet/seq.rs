@@ -0,0 +1,86 @@
+//! 序号宽度
+//!
+//! 序号在 `0 ..= mask` 的空间内回绕，比较大小时使用序号算术（RFC 1982），
+//! 只有相距不超过半个序号空间的两个序号才有先后关系
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SeqWidth {
+    #[default]
+    U8,
+    U16,
+    U32,
+}
+
+impl SeqWidth {
+    /// 序号最大值，同时也是回绕用的掩码
+    pub fn mask(&self) -> u32 {
+        match self {
+            SeqWidth::U8 => u8::MAX as u32,
+            SeqWidth::U16 => u16::MAX as u32,
+            SeqWidth::U32 => u32::MAX,
+        }
+    }
+
+    /// 写入 packet 时占用的 byte 数
+    pub fn bytes(&self) -> usize {
+        match self {
+            SeqWidth::U8 => 1,
+            SeqWidth::U16 => 2,
+            SeqWidth::U32 => 4,
+        }
+    }
+
+    /// `seq + n`，超出序号空间时回绕
+    pub fn add(&self, seq: u32, n: u32) -> u32 {
+        seq.wrapping_add(n) & self.mask()
+    }
+
+    /// 从 `start` 向前到 `seq` 的距离
+    pub fn sub(&self, seq: u32, start: u32) -> u32 {
+        seq.wrapping_sub(start) & self.mask()
+    }
+
+    /// 回绕意义下 `a` 是否在 `b` 之前
+    pub fn before(&self, a: u32, b: u32) -> bool {
+        let distance = self.sub(b, a);
+        distance != 0 && distance <= self.mask() / 2
+    }
+
+    /// `seq` 是否落在 `[start, start + len)` 内
+    pub fn in_range(&self, seq: u32, start: u32, len: u32) -> bool {
+        self.sub(seq, start) < len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SeqWidth;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(SeqWidth::U8.add(255, 1), 0);
+        assert_eq!(SeqWidth::U16.add(255, 1), 256);
+        assert_eq!(SeqWidth::U16.add(u16::MAX as u32, 2), 1);
+        assert_eq!(SeqWidth::U32.add(u32::MAX, 1), 0);
+
+        assert_eq!(SeqWidth::U8.sub(3, 250), 9);
+        assert_eq!(SeqWidth::U16.sub(3, 65530), 9);
+        assert_eq!(SeqWidth::U32.sub(3, u32::MAX - 5), 9);
+    }
+
+    #[test]
+    fn test_before() {
+        assert!(SeqWidth::U8.before(250, 3));
+        assert!(!SeqWidth::U8.before(3, 250));
+        assert!(!SeqWidth::U8.before(3, 3));
+        // 超过半个空间不再认为在之前
+        assert!(!SeqWidth::U8.before(0, 200));
+
+        assert!(SeqWidth::U16.before(65530, 3));
+        assert!(SeqWidth::U16.before(0, 200));
+        assert!(SeqWidth::U32.before(u32::MAX, 0));
+
+        assert!(SeqWidth::U16.in_range(1, 65530, 10));
+        assert!(!SeqWidth::U16.in_range(4, 65530, 10));
+    }
+}
@@ -0,0 +1,154 @@
+//! 关闭连接
+//!
+//! 发送端等待窗口内的 packet 全部被确认后发送 Leave，收到对应序号的 ACK 即关闭；
+//! Leave 超时会重传，超过 [`MAX_LEAVE_RETRY`] 次后不再等待确认直接关闭
+
+use tokio::sync::oneshot;
+
+use crate::{
+    packet::{leave::Leave, seq::SeqWidth, Packet},
+    verify::ChecksumKind,
+};
+
+use super::Timer;
+
+/// Leave 最多发送的次数
+pub const MAX_LEAVE_RETRY: u32 = 3;
+
+/// 发送端的关闭状态
+pub struct Closing {
+    /// 已发送且等待确认的 Leave 序号
+    leave: Option<u32>,
+    retries: u32,
+    /// Leave 重传定时器
+    timer: Option<Timer>,
+    notify: Option<oneshot::Sender<()>>,
+    closed: bool,
+}
+
+impl Closing {
+    /// 关闭完成时通知 `notify`
+    pub fn new(notify: oneshot::Sender<()>) -> Self {
+        Self {
+            leave: None,
+            retries: 0,
+            timer: None,
+            notify: Some(notify),
+            closed: false,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn leave_seq(&self) -> Option<u32> {
+        self.leave
+    }
+
+    /// 是否需要发送（或重传）Leave
+    pub fn need_leave(&self) -> bool {
+        !self.closed && self.leave.is_none()
+    }
+
+    /// 生成以 `seq` 为序号的 Leave，重传次数用尽时直接关闭并返回 None
+    pub fn leave(
+        &mut self,
+        seq: u32,
+        seq_width: SeqWidth,
+        checksum: ChecksumKind,
+    ) -> Option<Packet> {
+        if self.retries >= MAX_LEAVE_RETRY {
+            eprintln!(
+                "Leave [{seq}] not acknowledged after {} retries, close",
+                self.retries
+            );
+            self.finish();
+            return None;
+        }
+        self.retries += 1;
+        self.leave = Some(seq);
+        Some(
+            Leave::new_leave(seq)
+                .with_seq_width(seq_width)
+                .with_checksum(checksum),
+        )
+    }
+
+    pub fn set_timer(&mut self, timer: Timer) {
+        if let Some(t) = self.timer.replace(timer) {
+            t.stop();
+        }
+    }
+
+    /// Leave 超时，等待重传
+    pub fn on_timeout(&mut self) {
+        self.leave = None;
+    }
+
+    pub fn on_ack(&mut self, ack: u32) {
+        if self.leave == Some(ack) {
+            self.finish();
+        }
+    }
+
+    /// 结束关闭流程并通知等待方
+    pub fn finish(&mut self) {
+        self.closed = true;
+        self.leave = None;
+        if let Some(t) = self.timer.take() {
+            t.stop();
+        }
+        if let Some(notify) = self.notify.take() {
+            notify.send(()).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::sync::oneshot;
+
+    use crate::{packet::seq::SeqWidth, verify::ChecksumKind};
+
+    use super::{Closing, MAX_LEAVE_RETRY};
+
+    #[test]
+    fn test_closing() {
+        let (notify, mut closed) = oneshot::channel();
+        let mut closing = Closing::new(notify);
+        assert!(closing.need_leave());
+
+        let leave = closing
+            .leave(9, SeqWidth::U8, ChecksumKind::default())
+            .unwrap();
+        assert!(leave.is_leave());
+        assert!(!closing.need_leave());
+
+        // ack of other packet
+        closing.on_ack(8);
+        assert!(!closing.is_closed());
+
+        closing.on_ack(9);
+        assert!(closing.is_closed());
+        assert!(closed.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_leave_retry() {
+        let (notify, mut closed) = oneshot::channel();
+        let mut closing = Closing::new(notify);
+        for _ in 0..MAX_LEAVE_RETRY {
+            assert!(closing
+                .leave(0, SeqWidth::U8, ChecksumKind::default())
+                .is_some());
+            closing.on_timeout();
+        }
+
+        assert!(closing
+            .leave(0, SeqWidth::U8, ChecksumKind::default())
+            .is_none());
+        assert!(closing.is_closed());
+        assert!(closed.try_recv().is_ok());
+    }
+}
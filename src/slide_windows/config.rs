@@ -0,0 +1,202 @@
+//! 传输参数
+
+use std::time::Duration;
+
+use crate::{
+    packet::{handshake::ArqMode, seq::SeqWidth},
+    verify::ChecksumKind,
+};
+
+use super::{
+    congestion::CongestionKind, keepalive::KeepAliveConfig, retry::RetryPolicy,
+    DEFAULT_DUP_ACK_THRESHOLD, DEFAULT_MAX_MESSAGE, DEFAULT_MAX_PENDING, DEFAULT_MAX_SEGMENT,
+    TIMEOUT_MS,
+};
+
+/// 窗口大小的上限，缓冲区按窗口大小预先分配
+pub const MAX_WINDOW: u32 = 1 << 16;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ConfigError {
+    #[error("窗口大小不能为 0")]
+    ZeroWindow,
+    #[error("窗口大小 {window} 超出 {mode:?} 在 {width:?} 序号空间下的上限 {max}")]
+    WindowTooLarge {
+        window: u32,
+        max: u32,
+        mode: ArqMode,
+        width: SeqWidth,
+    },
+    #[error("最大分段长度不能为 0")]
+    ZeroSegment,
+    #[error("发送队列长度不能为 0")]
+    ZeroPending,
+    #[error("重传超时的抖动比例必须在 [0, 1) 之间")]
+    InvalidJitter,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TransportConfig {
+    /// 窗口大小，握手时与对端协商取较小值
+    pub window: u32,
+    /// 单个 packet body 的最大长度
+    pub max_segment: usize,
+    /// 重组消息长度上限
+    pub max_message: usize,
+    /// 等待进入窗口的消息数量上限，队列已满时发送方等待
+    pub max_pending: usize,
+    pub seq_width: SeqWidth,
+    pub checksum: ChecksumKind,
+    /// 尚未测得 RTT 时的重传超时
+    pub initial_rto: Duration,
+    pub keepalive: KeepAliveConfig,
+    pub retry: RetryPolicy,
+    /// Go back N 与选择重传使用的拥塞控制算法
+    pub congestion: CongestionKind,
+    /// Go back N 收到多少个重复 ACK 后立即重传，为 0 时只依靠超时重传
+    pub dup_ack_threshold: u32,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            window: 128,
+            max_segment: DEFAULT_MAX_SEGMENT,
+            max_message: DEFAULT_MAX_MESSAGE,
+            max_pending: DEFAULT_MAX_PENDING,
+            seq_width: SeqWidth::default(),
+            checksum: ChecksumKind::default(),
+            initial_rto: Duration::from_millis(TIMEOUT_MS),
+            keepalive: KeepAliveConfig::default(),
+            retry: RetryPolicy::default(),
+            congestion: CongestionKind::default(),
+            dup_ack_threshold: DEFAULT_DUP_ACK_THRESHOLD,
+        }
+    }
+}
+
+impl TransportConfig {
+    /// 序号空间允许的最大窗口
+    ///
+    /// Go back N 的窗口不能超过 `2 ^ k - 1`，否则无法区分新旧 packet；
+    /// 选择重传的发送与接收窗口之和不能超过 `2 ^ k`，因此窗口不能超过 `2 ^ (k - 1)`；
+    /// 停等协议同一时间只有一个 packet 等待确认，窗口大小只限制等待发送的分段数量
+    pub fn max_window(&self, mode: ArqMode) -> u32 {
+        let max = match mode {
+            ArqMode::GoBackN | ArqMode::StopAndWait => self.seq_width.mask(),
+            ArqMode::SelectResend => (self.seq_width.mask() >> 1) + 1,
+        };
+        max.min(MAX_WINDOW)
+    }
+
+    pub fn validate(&self, mode: ArqMode) -> Result<(), ConfigError> {
+        if self.window == 0 {
+            Err(ConfigError::ZeroWindow)?
+        }
+        let max = self.max_window(mode);
+        if self.window > max {
+            Err(ConfigError::WindowTooLarge {
+                window: self.window,
+                max,
+                mode,
+                width: self.seq_width,
+            })?
+        }
+        if self.max_segment == 0 {
+            Err(ConfigError::ZeroSegment)?
+        }
+        if self.max_pending == 0 {
+            Err(ConfigError::ZeroPending)?
+        }
+        // 抖动比例达到 1 时超时时间可能为 0，NaN 同样拒绝
+        if !(0.0..1.0).contains(&self.retry.jitter) {
+            Err(ConfigError::InvalidJitter)?
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::packet::{handshake::ArqMode, seq::SeqWidth};
+
+    use super::{ConfigError, RetryPolicy, TransportConfig, MAX_WINDOW};
+
+    #[test]
+    fn test_validate() {
+        let config = TransportConfig::default();
+        assert_eq!(config.validate(ArqMode::GoBackN), Ok(()));
+        assert_eq!(config.validate(ArqMode::SelectResend), Ok(()));
+
+        let config = TransportConfig {
+            window: 255,
+            ..Default::default()
+        };
+        assert_eq!(config.validate(ArqMode::GoBackN), Ok(()));
+        assert_eq!(config.validate(ArqMode::StopAndWait), Ok(()));
+        assert_eq!(
+            config.validate(ArqMode::SelectResend),
+            Err(ConfigError::WindowTooLarge {
+                window: 255,
+                max: 128,
+                mode: ArqMode::SelectResend,
+                width: SeqWidth::U8
+            })
+        );
+
+        let config = TransportConfig {
+            window: 1000,
+            seq_width: SeqWidth::U16,
+            ..Default::default()
+        };
+        assert_eq!(config.validate(ArqMode::SelectResend), Ok(()));
+        assert_eq!(config.max_window(ArqMode::SelectResend), 1 << 15);
+
+        let config = TransportConfig {
+            seq_width: SeqWidth::U32,
+            ..Default::default()
+        };
+        assert_eq!(config.max_window(ArqMode::GoBackN), MAX_WINDOW);
+
+        let config = TransportConfig {
+            window: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(ArqMode::GoBackN),
+            Err(ConfigError::ZeroWindow)
+        );
+
+        let config = TransportConfig {
+            max_segment: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(ArqMode::GoBackN),
+            Err(ConfigError::ZeroSegment)
+        );
+
+        let config = TransportConfig {
+            max_pending: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(ArqMode::GoBackN),
+            Err(ConfigError::ZeroPending)
+        );
+
+        for jitter in [-0.1, 1.0, f64::NAN] {
+            let config = TransportConfig {
+                retry: RetryPolicy {
+                    jitter,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(
+                config.validate(ArqMode::GoBackN),
+                Err(ConfigError::InvalidJitter)
+            );
+        }
+    }
+}
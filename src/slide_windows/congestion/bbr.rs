@@ -0,0 +1,278 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use super::{CongestionController, Loss, INITIAL_WINDOW, LOSS_WINDOW, MIN_WINDOW};
+
+/// 启动阶段的增益，每轮窗口翻倍
+const STARTUP_GAIN: f64 = 2.885;
+/// 带宽探测阶段每轮的发送速率增益，先多发探测更高的带宽，再少发排空队列
+const PROBE_BW_GAINS: [f64; 8] = [1.25, 0.75, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+/// 带宽探测阶段拥塞窗口相对带宽时延积的增益，容纳延迟到达的确认
+const CWND_GAIN: f64 = 2.0;
+/// 最大带宽取最近若干轮的样本
+const BW_WINDOW_ROUNDS: usize = 10;
+/// 带宽连续若干轮增长不足 25% 时认为已经填满链路
+const FULL_BW_ROUNDS: u32 = 3;
+/// 最小 RTT 超过该时间没有更新时重新测量
+const MIN_RTT_EXPIRE: Duration = Duration::from_secs(10);
+/// 测量最小 RTT 时缩小窗口的持续时间
+const PROBE_RTT_DURATION: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    /// 指数增长直到带宽不再增加
+    Startup,
+    /// 排空启动阶段在链路中堆积的队列
+    Drain,
+    /// 按照增益循环探测带宽
+    ProbeBw(usize),
+    /// 缩小窗口测量最小 RTT
+    ProbeRtt(Instant),
+}
+
+/// BBR 风格的拥塞控制
+///
+/// 以最近的最大投递速率与最小 RTT 估计链路的带宽时延积，
+/// 带宽探测阶段的拥塞窗口固定为带宽时延积的 [`CWND_GAIN`] 倍，
+/// 各阶段的增益只调整发送速率，随机丢包不会减小窗口
+#[derive(Debug, Clone)]
+pub struct Bbr {
+    mode: Mode,
+    max_window: u32,
+    /// 最小 RTT 与测得的时间
+    min_rtt: Option<(Duration, Instant)>,
+    /// 最近每轮的投递速率，packet 每秒
+    bw_samples: VecDeque<f64>,
+    /// 本轮开始的时间与已确认的 packet 数量
+    round_start: Option<Instant>,
+    round_delivered: u32,
+    /// 启动阶段的带宽记录及增长不足的轮数
+    full_bw: f64,
+    full_bw_rounds: u32,
+    in_flight: u32,
+    /// 超时后收到确认前只允许一个 packet 在途
+    conservation: bool,
+}
+
+impl Bbr {
+    pub fn new(max_window: u32) -> Self {
+        Self {
+            mode: Mode::Startup,
+            max_window,
+            min_rtt: None,
+            bw_samples: VecDeque::new(),
+            round_start: None,
+            round_delivered: 0,
+            full_bw: 0.0,
+            full_bw_rounds: 0,
+            in_flight: 0,
+            conservation: false,
+        }
+    }
+
+    fn max_bw(&self) -> f64 {
+        self.bw_samples.iter().copied().fold(0.0, f64::max)
+    }
+
+    /// 带宽时延积，尚未测得时为 None
+    fn bdp(&self) -> Option<f64> {
+        match (self.bw_samples.is_empty(), self.min_rtt) {
+            (false, Some((rtt, _))) => Some(self.max_bw() * rtt.as_secs_f64()),
+            _ => None,
+        }
+    }
+
+    /// 按当前阶段的增益调整的发送速率，packet 每秒，尚未测得带宽时为 None
+    ///
+    /// 发送端目前只按拥塞窗口发送，没有使用发送速率
+    pub fn pacing_rate(&self) -> Option<f64> {
+        let gain = match self.mode {
+            Mode::Startup => STARTUP_GAIN,
+            Mode::Drain => 1.0 / STARTUP_GAIN,
+            Mode::ProbeBw(phase) => PROBE_BW_GAINS[phase],
+            Mode::ProbeRtt(_) => 1.0,
+        };
+        (!self.bw_samples.is_empty()).then(|| self.max_bw() * gain)
+    }
+
+    /// 拥塞窗口相对带宽时延积的增益
+    fn cwnd_gain(&self) -> f64 {
+        match self.mode {
+            Mode::Startup => STARTUP_GAIN,
+            // 发送端没有按速率发送，只能把窗口限制在带宽时延积以内排空队列
+            Mode::Drain => 1.0,
+            Mode::ProbeBw(_) | Mode::ProbeRtt(_) => CWND_GAIN,
+        }
+    }
+
+    fn update_min_rtt(&mut self, now: Instant, rtt: Duration) {
+        // 更小的样本或旧的最小值已经过期时替换
+        let replace = |&(min, at): &(Duration, Instant)| {
+            rtt <= min || now.saturating_duration_since(at) > MIN_RTT_EXPIRE
+        };
+        if self.min_rtt.as_ref().is_none_or(replace) {
+            self.min_rtt = Some((rtt, now));
+        }
+    }
+
+    /// 每经过一个最小 RTT 记录一次投递速率并推进状态
+    ///
+    /// 第一次确认开始计时，其确认的 packet 计入第一轮
+    fn update_round(&mut self, now: Instant, acked: u32) {
+        let start = *self.round_start.get_or_insert(now);
+        self.round_delivered += acked;
+        let elapsed = now.saturating_duration_since(start);
+        let round = self.min_rtt.map_or(Duration::ZERO, |(rtt, _)| rtt);
+        if elapsed.is_zero() || elapsed < round {
+            return;
+        }
+
+        self.bw_samples
+            .push_back(self.round_delivered as f64 / elapsed.as_secs_f64());
+        if self.bw_samples.len() > BW_WINDOW_ROUNDS {
+            self.bw_samples.pop_front();
+        }
+        self.round_start = Some(now);
+        self.round_delivered = 0;
+
+        match self.mode {
+            Mode::Startup => {
+                let max_bw = self.max_bw();
+                if max_bw >= self.full_bw * 1.25 {
+                    self.full_bw = max_bw;
+                    self.full_bw_rounds = 0;
+                } else {
+                    self.full_bw_rounds += 1;
+                }
+                if self.full_bw_rounds >= FULL_BW_ROUNDS {
+                    self.mode = Mode::Drain;
+                }
+            }
+            Mode::ProbeBw(phase) => self.mode = Mode::ProbeBw((phase + 1) % PROBE_BW_GAINS.len()),
+            _ => (),
+        }
+    }
+}
+
+impl CongestionController for Bbr {
+    fn window(&self) -> u32 {
+        if self.conservation {
+            return LOSS_WINDOW;
+        }
+        match (self.mode, self.bdp()) {
+            (Mode::ProbeRtt(_), _) => INITIAL_WINDOW.min(self.max_window),
+            (_, None) => INITIAL_WINDOW.min(self.max_window),
+            (_, Some(bdp)) => ((bdp * self.cwnd_gain()) as u32).clamp(MIN_WINDOW, self.max_window),
+        }
+    }
+
+    fn on_send(&mut self, _now: Instant, packets: u32) {
+        self.in_flight += packets;
+    }
+
+    fn on_ack(&mut self, now: Instant, acked: u32, rtt: Option<Duration>) {
+        self.in_flight = self.in_flight.saturating_sub(acked);
+        self.conservation = false;
+        if let Some(rtt) = rtt {
+            self.update_min_rtt(now, rtt);
+        }
+        self.update_round(now, acked);
+
+        match self.mode {
+            Mode::Drain if self.bdp().is_some_and(|bdp| self.in_flight as f64 <= bdp) => {
+                self.mode = Mode::ProbeBw(0);
+            }
+            Mode::ProbeRtt(start) if now.saturating_duration_since(start) >= PROBE_RTT_DURATION => {
+                if let Some((_, at)) = self.min_rtt.as_mut() {
+                    *at = now;
+                }
+                self.mode = Mode::ProbeBw(0);
+            }
+            Mode::ProbeBw(_)
+                if self
+                    .min_rtt
+                    .is_some_and(|(_, at)| now.saturating_duration_since(at) > MIN_RTT_EXPIRE) =>
+            {
+                self.mode = Mode::ProbeRtt(now);
+            }
+            _ => (),
+        }
+    }
+
+    fn on_loss(&mut self, _now: Instant, loss: Loss, in_flight: u32, lost: u32) {
+        self.in_flight = in_flight.saturating_sub(lost);
+        if loss == Loss::Timeout {
+            self.conservation = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::slide_windows::congestion::{CongestionController, Loss, INITIAL_WINDOW};
+
+    use super::{Bbr, Mode, CWND_GAIN, PROBE_BW_GAINS, STARTUP_GAIN};
+
+    #[test]
+    fn test_bbr() {
+        let start = Instant::now();
+        let rtt = Duration::from_millis(100);
+        let mut bbr = Bbr::new(1000);
+        assert_eq!(bbr.window(), INITIAL_WINDOW);
+
+        // 每个 RTT 确认 50 个 packet，第一轮从第一次确认开始计时，
+        // 一个 RTT 内共确认 100 个，最大投递速率为 1000 packet 每秒，带宽时延积为 100
+        let bdp = 100.0;
+        bbr.on_send(start, 400);
+        bbr.on_ack(start, 50, Some(rtt));
+        bbr.on_ack(start + rtt, 50, Some(rtt));
+        assert_eq!(bbr.mode, Mode::Startup);
+        assert_eq!(bbr.window(), (bdp * STARTUP_GAIN) as u32);
+        assert_eq!(bbr.pacing_rate(), Some(1000.0 * STARTUP_GAIN));
+
+        // 之后每轮只有 500 packet 每秒，连续三轮没有增长，排空队列
+        for round in 2..=4 {
+            bbr.on_ack(start + rtt * round, 50, Some(rtt));
+        }
+        assert_eq!(bbr.mode, Mode::Drain);
+        assert_eq!(bbr.in_flight, 150);
+        assert_eq!(bbr.window(), bdp as u32);
+
+        // 在途数量降到带宽时延积以下后开始探测带宽
+        let now = start + rtt * 5;
+        bbr.on_ack(now, 50, Some(rtt));
+        assert_eq!(bbr.mode, Mode::ProbeBw(0));
+        assert_eq!(bbr.in_flight, 100);
+        assert_eq!(bbr.window(), (bdp * CWND_GAIN) as u32);
+
+        // 随机丢包不减小窗口，重传的 packet 重新计入在途数量
+        bbr.on_loss(now, Loss::Fast, 100, 1);
+        bbr.on_send(now, 1);
+        assert_eq!(bbr.in_flight, 100);
+        assert_eq!(bbr.window(), (bdp * CWND_GAIN) as u32);
+        // 超时后收到确认前只允许一个 packet 在途
+        bbr.on_loss(now, Loss::Timeout, 100, 1);
+        bbr.on_send(now, 1);
+        assert_eq!(bbr.window(), 1);
+        bbr.on_ack(now + rtt / 2, 1, None);
+        assert_eq!(bbr.window(), (bdp * CWND_GAIN) as u32);
+
+        // 下一轮降低增益只减小发送速率，拥塞窗口不变
+        bbr.on_ack(now + rtt, 49, None);
+        assert_eq!(bbr.mode, Mode::ProbeBw(1));
+        assert_eq!(bbr.window(), (bdp * CWND_GAIN) as u32);
+        assert_eq!(bbr.pacing_rate(), Some(1000.0 * PROBE_BW_GAINS[1]));
+
+        // 最小 RTT 长时间没有更新时重新测量
+        let now = start + Duration::from_secs(13);
+        bbr.on_ack(now, 1, None);
+        assert!(matches!(bbr.mode, Mode::ProbeRtt(_)));
+        assert_eq!(bbr.window(), INITIAL_WINDOW);
+        bbr.on_ack(now + Duration::from_millis(250), 1, None);
+        assert_eq!(bbr.mode, Mode::ProbeBw(0));
+    }
+}
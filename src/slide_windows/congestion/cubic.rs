@@ -0,0 +1,161 @@
+use std::time::{Duration, Instant};
+
+use super::{CongestionController, Loss, INITIAL_WINDOW, LOSS_WINDOW, MIN_WINDOW};
+
+/// 三次函数的缩放系数
+const C: f64 = 0.4;
+/// 丢包后窗口的保留比例
+const BETA: f64 = 0.7;
+
+/// CUBIC（RFC 9438）
+///
+/// 拥塞避免阶段窗口按照距离上次丢包的时间以三次函数增长，
+/// 增长速度与 RTT 无关，适合高延迟的链路
+#[derive(Debug, Clone)]
+pub struct Cubic {
+    cwnd: f64,
+    ssthresh: f64,
+    max_window: u32,
+    /// 上次丢包前的窗口
+    w_max: f64,
+    /// 窗口增长回 `w_max` 所需的秒数
+    k: f64,
+    /// 本轮拥塞避免开始的时间
+    epoch: Option<Instant>,
+    /// 按照 Reno 增长时的窗口，CUBIC 不应比 Reno 更慢
+    w_est: f64,
+    min_rtt: Option<Duration>,
+    /// 丢包时在途的 packet 全部确认前处于恢复阶段
+    recover: u32,
+}
+
+impl Cubic {
+    pub fn new(max_window: u32) -> Self {
+        Self {
+            cwnd: INITIAL_WINDOW.min(max_window) as f64,
+            ssthresh: max_window as f64,
+            max_window,
+            w_max: 0.0,
+            k: 0.0,
+            epoch: None,
+            w_est: 0.0,
+            min_rtt: None,
+            recover: 0,
+        }
+    }
+
+    fn start_epoch(&mut self, now: Instant) -> Instant {
+        if self.cwnd < self.w_max {
+            self.k = ((self.w_max - self.cwnd) / C).cbrt();
+        } else {
+            self.k = 0.0;
+            self.w_max = self.cwnd;
+        }
+        self.w_est = self.cwnd;
+        self.epoch = Some(now);
+        now
+    }
+}
+
+impl CongestionController for Cubic {
+    fn window(&self) -> u32 {
+        (self.cwnd as u32).clamp(LOSS_WINDOW, self.max_window)
+    }
+
+    fn on_ack(&mut self, now: Instant, acked: u32, rtt: Option<Duration>) {
+        if let Some(rtt) = rtt {
+            self.min_rtt = Some(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+        }
+        self.recover = self.recover.saturating_sub(acked);
+        let acked = acked as f64;
+
+        if self.cwnd < self.ssthresh {
+            self.cwnd += acked;
+        } else {
+            let epoch = match self.epoch {
+                Some(epoch) => epoch,
+                None => self.start_epoch(now),
+            };
+            // 以一个 RTT 之后的目标窗口为准
+            let t = (now.saturating_duration_since(epoch) + self.min_rtt.unwrap_or_default())
+                .as_secs_f64();
+            let target = (C * (t - self.k).powi(3) + self.w_max).clamp(self.cwnd, self.cwnd * 1.5);
+            self.w_est += acked * (3.0 * (1.0 - BETA) / (1.0 + BETA)) / self.cwnd;
+
+            if self.w_est > target {
+                self.cwnd = self.w_est;
+            } else {
+                self.cwnd += (target - self.cwnd) / self.cwnd * acked;
+            }
+        }
+        self.cwnd = self.cwnd.min(self.max_window as f64);
+    }
+
+    fn on_loss(&mut self, _now: Instant, loss: Loss, in_flight: u32, _lost: u32) {
+        let in_recovery = self.recover > 0;
+        if !in_recovery {
+            // 窗口还没有恢复到上次丢包前的大小，说明可用带宽在减小，提前让出带宽
+            self.w_max = if self.cwnd < self.w_max {
+                self.cwnd * (1.0 + BETA) / 2.0
+            } else {
+                self.cwnd
+            };
+            self.ssthresh = (self.cwnd * BETA).max(MIN_WINDOW as f64);
+            self.recover = in_flight;
+            self.epoch = None;
+        }
+        match loss {
+            Loss::Timeout => {
+                self.cwnd = LOSS_WINDOW as f64;
+                self.epoch = None;
+            }
+            Loss::Fast if !in_recovery => self.cwnd = self.ssthresh,
+            Loss::Fast => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::slide_windows::congestion::{CongestionController, Loss};
+
+    use super::Cubic;
+
+    #[test]
+    fn test_cubic() {
+        let now = Instant::now();
+        let mut cubic = Cubic::new(100);
+
+        // 慢启动
+        cubic.on_ack(now, 16, None);
+        assert_eq!(cubic.window(), 20);
+
+        // 丢包后保留 0.7 倍
+        cubic.on_loss(now, Loss::Fast, 20, 1);
+        assert_eq!(cubic.window(), 14);
+        cubic.on_loss(now, Loss::Fast, 20, 1);
+        assert_eq!(cubic.window(), 14);
+
+        // 经过 K 秒后回到丢包前的窗口
+        cubic.on_ack(now, 1, None);
+        assert_eq!(cubic.window(), 14);
+        let k = Duration::from_secs_f64(cubic.k);
+        cubic.on_ack(now + k, 14, None);
+        assert!((19..=20).contains(&cubic.window()));
+
+        // 之后加速增长
+        cubic.on_ack(now + k + Duration::from_secs(5), 20, None);
+        assert!(cubic.window() > 25);
+
+        // 未恢复到上次窗口时再次丢包，快速收敛
+        let mut cubic = Cubic::new(100);
+        cubic.on_ack(now, 16, None);
+        cubic.on_loss(now, Loss::Fast, 20, 1);
+        cubic.on_ack(now, 20, None);
+        cubic.on_loss(now, Loss::Timeout, 14, 1);
+        assert_eq!(cubic.window(), 1);
+        assert!(cubic.w_max < 14.0);
+    }
+}
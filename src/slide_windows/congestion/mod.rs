@@ -0,0 +1,68 @@
+//! 拥塞控制
+//!
+//! 发送端在发送、确认与丢包时通知控制器，
+//! 在途 packet 数量不超过控制器给出的拥塞窗口
+
+use std::time::{Duration, Instant};
+
+mod bbr;
+mod cubic;
+mod reno;
+
+pub use bbr::Bbr;
+pub use cubic::Cubic;
+pub use reno::NewReno;
+
+/// 初始拥塞窗口
+pub const INITIAL_WINDOW: u32 = 4;
+/// 拥塞窗口的下限
+pub const MIN_WINDOW: u32 = 2;
+/// 超时后的拥塞窗口
+pub const LOSS_WINDOW: u32 = 1;
+
+/// 丢包的判断依据
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loss {
+    /// 重传超时，链路可能已经严重拥塞
+    Timeout,
+    /// NAK 等提前得知的丢包，之后的 packet 仍在到达
+    Fast,
+}
+
+pub trait CongestionController: Send {
+    /// 当前允许在途的 packet 数量
+    fn window(&self) -> u32;
+
+    /// 发送了 `packets` 个 packet，重传的 packet 同样计入
+    fn on_send(&mut self, _now: Instant, _packets: u32) {}
+
+    /// 新确认了 `acked` 个 packet，`rtt` 为本次确认得到的 RTT 样本
+    fn on_ack(&mut self, now: Instant, acked: u32, rtt: Option<Duration>);
+
+    /// 检测到丢包，`in_flight` 为当时在途的 packet 数量，其中 `lost` 个被判定丢失，
+    /// 不再计入在途数量，重传时重新通过 [`on_send`](Self::on_send) 计入
+    fn on_loss(&mut self, now: Instant, loss: Loss, in_flight: u32, lost: u32);
+}
+
+/// 拥塞控制算法，按连接选择
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CongestionKind {
+    /// 慢启动、拥塞避免与乘性减小（RFC 6582）
+    #[default]
+    NewReno,
+    /// 三次函数增长的 CUBIC（RFC 9438），适合高带宽时延积的链路
+    Cubic,
+    /// 以瓶颈带宽与最小 RTT 建模的 BBR 风格算法，不把随机丢包视为拥塞
+    Bbr,
+}
+
+impl CongestionKind {
+    /// 创建控制器，拥塞窗口不超过 `max_window`
+    pub fn controller(&self, max_window: u32) -> Box<dyn CongestionController> {
+        match self {
+            CongestionKind::NewReno => Box::new(NewReno::new(max_window)),
+            CongestionKind::Cubic => Box::new(Cubic::new(max_window)),
+            CongestionKind::Bbr => Box::new(Bbr::new(max_window)),
+        }
+    }
+}
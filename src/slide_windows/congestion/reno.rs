@@ -0,0 +1,113 @@
+use std::time::{Duration, Instant};
+
+use super::{CongestionController, Loss, INITIAL_WINDOW, LOSS_WINDOW, MIN_WINDOW};
+
+/// NewReno
+///
+/// 慢启动阶段每确认一个 packet 窗口加一，超过阈值后每个窗口加一；
+/// 丢包时阈值减半，同一窗口内的多次丢包只减小一次
+#[derive(Debug, Clone)]
+pub struct NewReno {
+    cwnd: u32,
+    ssthresh: u32,
+    max_window: u32,
+    /// 拥塞避免阶段累计确认的 packet 数量
+    acked: u32,
+    /// 丢包时在途的 packet 全部确认前处于恢复阶段
+    recover: u32,
+}
+
+impl NewReno {
+    pub fn new(max_window: u32) -> Self {
+        Self {
+            cwnd: INITIAL_WINDOW.min(max_window),
+            ssthresh: max_window,
+            max_window,
+            acked: 0,
+            recover: 0,
+        }
+    }
+
+    pub fn ssthresh(&self) -> u32 {
+        self.ssthresh
+    }
+}
+
+impl CongestionController for NewReno {
+    fn window(&self) -> u32 {
+        self.cwnd
+    }
+
+    fn on_ack(&mut self, _now: Instant, acked: u32, _rtt: Option<Duration>) {
+        self.recover = self.recover.saturating_sub(acked);
+        if self.cwnd < self.ssthresh {
+            self.cwnd += acked;
+        } else {
+            self.acked += acked;
+            while self.acked >= self.cwnd {
+                self.acked -= self.cwnd;
+                self.cwnd += 1;
+            }
+        }
+        self.cwnd = self.cwnd.min(self.max_window);
+    }
+
+    fn on_loss(&mut self, _now: Instant, loss: Loss, in_flight: u32, _lost: u32) {
+        let in_recovery = self.recover > 0;
+        if !in_recovery {
+            self.ssthresh = (in_flight / 2).max(MIN_WINDOW);
+            self.recover = in_flight;
+        }
+        self.acked = 0;
+        match loss {
+            Loss::Timeout => self.cwnd = LOSS_WINDOW,
+            Loss::Fast if !in_recovery => self.cwnd = self.ssthresh,
+            Loss::Fast => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use crate::slide_windows::congestion::{CongestionController, Loss, INITIAL_WINDOW};
+
+    use super::NewReno;
+
+    #[test]
+    fn test_new_reno() {
+        let now = Instant::now();
+        let mut reno = NewReno::new(64);
+        assert_eq!(reno.window(), INITIAL_WINDOW);
+
+        // 慢启动，每个确认窗口加一
+        reno.on_ack(now, 4, None);
+        assert_eq!(reno.window(), 8);
+
+        // 乘性减小，进入拥塞避免
+        reno.on_loss(now, Loss::Fast, 8, 1);
+        assert_eq!(reno.ssthresh(), 4);
+        assert_eq!(reno.window(), 4);
+        // 同一窗口内的丢包只减小一次
+        reno.on_loss(now, Loss::Fast, 8, 1);
+        assert_eq!(reno.window(), 4);
+
+        // 拥塞避免，每确认一个窗口加一
+        reno.on_ack(now, 3, None);
+        assert_eq!(reno.window(), 4);
+        reno.on_ack(now, 1, None);
+        assert_eq!(reno.window(), 5);
+
+        // 超时回到慢启动
+        reno.on_ack(now, 8, None);
+        reno.on_loss(now, Loss::Timeout, 6, 1);
+        assert_eq!(reno.window(), 1);
+        assert_eq!(reno.ssthresh(), 3);
+
+        // 不超过窗口上限
+        let mut reno = NewReno::new(6);
+        reno.on_ack(now, 10, None);
+        assert_eq!(reno.window(), 6);
+    }
+}
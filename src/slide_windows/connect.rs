@@ -0,0 +1,476 @@
+//! 连接建立
+//!
+//! 发送端通过 SYN 携带随机的初始序号、ARQ 模式与期望窗口发起连接，
+//! 接收端以 SYN-ACK 回复协商后的窗口，发送端再以 ACK 确认，之后才开始发送数据。
+//! 接收端没有连接时收到新的初始序号才会重置接收状态。连接结束前收到其他初始序号的 SYN
+//! 时回复携带当前初始序号的 SYN-ACK，延迟到达的旧 SYN 不会影响连接，
+//! 重启后的发送端则据此回复 Abort 结束旧连接；没有连接时收到数据回复 Reset
+//!
+//! 三种 ARQ 的接收端共用 [`Acceptor`] 回复 Leave 与 Ping，并统计解码失败
+
+use std::{collections::VecDeque, io, net::SocketAddr};
+
+use crate::{
+    fake_udp::UdpSocket,
+    packet::{
+        ack::Ack,
+        handshake::{ArqMode, Handshake, HandshakeStage},
+        seq::SeqWidth,
+        Packet, PacketDecodeError,
+    },
+    verify::ChecksumKind,
+};
+
+use super::{DecodeErrorStats, Message, Timer};
+
+#[derive(Debug, thiserror::Error)]
+pub enum HandshakeError {
+    #[error("ARQ 模式不匹配，期望 {expect:?} 实际 {actual:?}")]
+    ModeMismatch { expect: ArqMode, actual: ArqMode },
+    #[error("初始序号不匹配，期望 {expect} 实际 {actual}")]
+    IsnMismatch { expect: u32, actual: u32 },
+    #[error("对端仍保留初始序号为 {current} 的旧连接")]
+    Challenged { current: u32 },
+}
+
+/// 握手完成后协商得到的连接参数
+#[derive(Debug)]
+pub struct Established {
+    pub isn: u32,
+    pub window: u32,
+    /// 握手期间缓存的消息
+    pub pending: VecDeque<Message>,
+}
+
+/// 发送端的握手状态
+pub struct Connector {
+    mode: ArqMode,
+    seq_width: SeqWidth,
+    checksum: ChecksumKind,
+    /// 本端期望的窗口大小
+    window: u32,
+    isn: u32,
+    established: bool,
+    /// SYN 重传定时器
+    timer: Option<Timer>,
+    pending: VecDeque<Message>,
+}
+
+impl Connector {
+    pub fn new(mode: ArqMode, window: u32, seq_width: SeqWidth, checksum: ChecksumKind) -> Self {
+        Self {
+            mode,
+            seq_width,
+            checksum,
+            window,
+            isn: rand::random::<u32>() & seq_width.mask(),
+            established: false,
+            timer: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn isn(&self) -> u32 {
+        self.isn
+    }
+
+    pub fn is_established(&self) -> bool {
+        self.established
+    }
+
+    /// 使用新的初始序号重新发起连接
+    pub fn restart(&mut self) {
+        self.isn = rand::random::<u32>() & self.seq_width.mask();
+        self.established = false;
+    }
+
+    /// 连接建立前缓存消息
+    pub fn queue(&mut self, msg: Message) {
+        self.pending.push_back(msg);
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// 丢弃连接建立前缓存的消息
+    pub fn clear_pending(&mut self) {
+        self.pending.clear();
+    }
+
+    /// 记录 SYN 的重传定时器，替换旧的定时器
+    pub fn set_timer(&mut self, timer: Timer) {
+        if let Some(t) = self.timer.replace(timer) {
+            t.stop();
+        }
+    }
+
+    pub fn stop_timer(&mut self) {
+        if let Some(t) = self.timer.take() {
+            t.stop();
+        }
+    }
+
+    pub fn syn(&self) -> Packet {
+        self.packet(HandshakeStage::Syn, self.window)
+    }
+
+    pub fn ack(&self) -> Packet {
+        self.packet(HandshakeStage::Ack, self.window)
+    }
+
+    /// 处理 SYN-ACK，首次收到时返回协商结果，重复的 SYN-ACK 返回 None
+    ///
+    /// 连接建立前收到其他初始序号的 SYN-ACK，说明对端仍保留着旧连接，返回
+    /// `HandshakeError::Challenged`，发送端需要回复 Abort
+    pub fn on_syn_ack(
+        &mut self,
+        syn_ack: &Handshake,
+    ) -> Result<Option<Established>, HandshakeError> {
+        if syn_ack.mode != self.mode {
+            Err(HandshakeError::ModeMismatch {
+                expect: self.mode,
+                actual: syn_ack.mode,
+            })?
+        }
+        if syn_ack.isn != self.isn && !self.established {
+            Err(HandshakeError::Challenged {
+                current: syn_ack.isn,
+            })?
+        }
+        if syn_ack.isn != self.isn {
+            Err(HandshakeError::IsnMismatch {
+                expect: self.isn,
+                actual: syn_ack.isn,
+            })?
+        }
+        if self.established {
+            return Ok(None);
+        }
+
+        self.established = true;
+        self.stop_timer();
+        Ok(Some(Established {
+            isn: self.isn,
+            window: syn_ack.window.clamp(1, self.window),
+            pending: std::mem::take(&mut self.pending),
+        }))
+    }
+
+    /// 要求对端结束初始序号为 `current` 的旧连接
+    pub fn abort(&self, current: u32) -> Packet {
+        let handshake = Handshake {
+            stage: HandshakeStage::Abort,
+            isn: current,
+            mode: self.mode,
+            window: self.window,
+        };
+        Packet::new_handshake(&handshake, self.seq_width).with_checksum(self.checksum)
+    }
+
+    fn packet(&self, stage: HandshakeStage, window: u32) -> Packet {
+        let handshake = Handshake {
+            stage,
+            isn: self.isn,
+            mode: self.mode,
+            window,
+        };
+        Packet::new_handshake(&handshake, self.seq_width).with_checksum(self.checksum)
+    }
+}
+
+/// 接收端的连接状态，三种 ARQ 的接收端共用握手、Leave、Ping 与解码失败的处理
+pub struct Acceptor {
+    origin: SocketAddr,
+    mode: ArqMode,
+    seq_width: SeqWidth,
+    checksum: ChecksumKind,
+    /// 本端能接受的最大窗口
+    window: u32,
+    /// 当前连接的初始序号
+    session: Option<u32>,
+    decode_errors: DecodeErrorStats,
+}
+
+impl Acceptor {
+    pub fn new(
+        origin: SocketAddr,
+        mode: ArqMode,
+        window: u32,
+        seq_width: SeqWidth,
+        checksum: ChecksumKind,
+    ) -> Self {
+        Self {
+            origin,
+            mode,
+            seq_width,
+            checksum,
+            window,
+            session: None,
+            decode_errors: DecodeErrorStats::default(),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.session.is_some()
+    }
+
+    /// 处理 SYN，返回需要回复的 SYN-ACK
+    ///
+    /// 建立新连接时同时返回新的初始序号，接收端需要据此重置状态；
+    /// 连接结束前收到其他初始序号的 SYN 时回复携带当前初始序号的 SYN-ACK，
+    /// 不重置正在进行的连接；ARQ 模式不同时不建立连接，SYN-ACK 携带本端模式由发送端报错
+    pub fn on_syn(&mut self, syn: &Handshake) -> Result<(Packet, Option<u32>), HandshakeError> {
+        if syn.mode != self.mode {
+            return Ok((
+                self.packet(HandshakeStage::SynAck, syn.isn, self.window),
+                None,
+            ));
+        }
+
+        let window = syn.window.clamp(1, self.window);
+        let reply = self.packet(HandshakeStage::SynAck, syn.isn, window);
+        match self.session {
+            // SYN 重传，连接已经建立
+            Some(current) if current == syn.isn => Ok((reply, None)),
+            Some(current) => Ok((self.packet(HandshakeStage::SynAck, current, window), None)),
+            None => {
+                self.session = Some(syn.isn);
+                Ok((reply, Some(syn.isn)))
+            }
+        }
+    }
+
+    /// 收到 Leave 后结束当前连接，之后可以接受新的初始序号
+    pub fn close(&mut self) {
+        self.session = None;
+    }
+
+    /// 收到与当前连接对应的 Abort 时结束连接，返回是否结束
+    pub fn on_abort(&mut self, abort: &Handshake) -> bool {
+        if self.session != Some(abort.isn) {
+            return false;
+        }
+        self.session = None;
+        true
+    }
+
+    /// 处理发送端的 SYN、ACK 与 Abort，建立新连接时返回新的初始序号，接收端需要据此重置状态
+    pub async fn recv_handshake<E>(
+        &mut self,
+        handshake: &Handshake,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> Result<Option<u32>, E>
+    where
+        E: From<HandshakeError> + From<io::Error>,
+    {
+        match handshake.stage {
+            HandshakeStage::Syn => {
+                let (reply, reset) = self.on_syn(handshake)?;
+                if let Some(isn) = reset {
+                    println!("New connection from {} isn [{isn}]", self.origin);
+                }
+                self.send(&reply, buf, socket).await?;
+                return Ok(reset);
+            }
+            HandshakeStage::Ack => println!("Connection from {} established", self.origin),
+            // 发送端重启后要求结束旧连接，之后的 SYN 会重置接收状态
+            HandshakeStage::Abort if self.on_abort(handshake) => {
+                println!("Connection from {} aborted by peer", self.origin)
+            }
+            _ => (),
+        }
+
+        Ok(None)
+    }
+
+    /// 没有连接时拒绝序号为 `seq` 的 packet，要求发送端重新握手
+    ///
+    /// Reset 携带被拒绝的序号，发送端据此确认 Reset 属于当前连接
+    pub async fn send_reset(
+        &self,
+        seq: u32,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> io::Result<()> {
+        let reset = self.packet(HandshakeStage::Reset, seq, self.window);
+        self.send(&reset, buf, socket).await
+    }
+
+    /// 处理 Leave，`expect` 为期望收到的下一个序号，
+    /// 发送端的数据全部按序收到后确认并返回 true，接收端随后结束
+    ///
+    /// 没有连接时（例如接收端重启过）同样确认，避免发送端一直重传
+    pub async fn recv_leave(
+        &mut self,
+        leave_id: u32,
+        expect: u32,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> io::Result<bool> {
+        if self.is_connected() && leave_id != expect {
+            println!("Leave [{leave_id}] before packet [{expect}], ignore");
+            return Ok(false);
+        }
+
+        let ack = Ack::new_ack(leave_id)
+            .with_seq_width(self.seq_width)
+            .with_checksum(self.checksum);
+        self.send(&ack, buf, socket).await?;
+
+        self.close();
+        Ok(true)
+    }
+
+    /// 回复发送端的 Ping
+    pub async fn send_pong(
+        &self,
+        ping_id: u32,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> io::Result<()> {
+        let pong = Packet::new_pong(ping_id)
+            .with_seq_width(self.seq_width)
+            .with_checksum(self.checksum);
+        self.send(&pong, buf, socket).await
+    }
+
+    /// 记录一次解码失败，返回原错误便于继续传递
+    pub fn record_decode_error(&mut self, err: PacketDecodeError) -> PacketDecodeError {
+        self.decode_errors.record(&err);
+        eprintln!(
+            "Decode Failure from {} [{err}], total {}",
+            self.origin,
+            self.decode_errors.total()
+        );
+        err
+    }
+
+    pub fn decode_errors(&self) -> &DecodeErrorStats {
+        &self.decode_errors
+    }
+
+    async fn send(&self, packet: &Packet, buf: &mut Vec<u8>, socket: &UdpSocket) -> io::Result<()> {
+        buf.clear();
+        let size = packet.write(buf)?;
+        socket.send_to(&buf[0..size], self.origin).await?;
+        Ok(())
+    }
+
+    fn packet(&self, stage: HandshakeStage, isn: u32, window: u32) -> Packet {
+        let handshake = Handshake {
+            stage,
+            isn,
+            mode: self.mode,
+            window,
+        };
+        Packet::new_handshake(&handshake, self.seq_width).with_checksum(self.checksum)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use crate::{
+        packet::{
+            handshake::{ArqMode, HandshakeStage},
+            seq::SeqWidth,
+        },
+        verify::ChecksumKind,
+    };
+
+    use super::{Acceptor, Connector, HandshakeError};
+
+    #[test]
+    fn test_handshake() {
+        let mut connector = Connector::new(
+            ArqMode::SelectResend,
+            200,
+            SeqWidth::U16,
+            ChecksumKind::default(),
+        );
+        let mut acceptor = Acceptor::new(
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            ArqMode::SelectResend,
+            128,
+            SeqWidth::U16,
+            ChecksumKind::default(),
+        );
+        connector.queue(vec![1].into());
+
+        let syn = connector.syn().as_handshake().unwrap();
+        assert_eq!(syn.stage, HandshakeStage::Syn);
+        let (syn_ack, reset) = acceptor.on_syn(&syn).unwrap();
+        assert_eq!(reset, Some(connector.isn()));
+        assert!(acceptor.is_connected());
+
+        // SYN 重传不会再次重置
+        assert_eq!(acceptor.on_syn(&syn).unwrap().1, None);
+
+        let syn_ack = syn_ack.as_handshake().unwrap();
+        let established = connector.on_syn_ack(&syn_ack).unwrap().unwrap();
+        assert_eq!(established.window, 128);
+        assert_eq!(established.pending.len(), 1);
+        assert_eq!(established.pending[0].body, [1]);
+        assert!(connector.is_established());
+        assert!(connector.on_syn_ack(&syn_ack).unwrap().is_none());
+
+        // 连接结束前，其他初始序号的 SYN 只得到携带当前初始序号的 SYN-ACK
+        let isn = connector.isn();
+        let mut restarted = Connector::new(
+            ArqMode::SelectResend,
+            200,
+            SeqWidth::U16,
+            ChecksumKind::default(),
+        );
+        while restarted.isn() == isn {
+            restarted.restart();
+        }
+        let syn = restarted.syn().as_handshake().unwrap();
+        let (challenge, reset) = acceptor.on_syn(&syn).unwrap();
+        assert_eq!(reset, None);
+        assert!(acceptor.is_connected());
+
+        // 已建立连接的发送端只把它当作重复的 SYN-ACK
+        let challenge = challenge.as_handshake().unwrap();
+        assert_eq!(challenge.isn, isn);
+        assert!(connector.on_syn_ack(&challenge).unwrap().is_none());
+
+        // 重启后的发送端回复 Abort 结束旧连接，之后重传的 SYN 建立新连接
+        assert!(matches!(
+            restarted.on_syn_ack(&challenge),
+            Err(HandshakeError::Challenged { current }) if current == isn
+        ));
+        let abort = restarted.abort(isn).as_handshake().unwrap();
+        let mut stale = abort.clone();
+        stale.isn = isn.wrapping_add(1);
+        assert!(!acceptor.on_abort(&stale));
+        assert!(acceptor.on_abort(&abort));
+        assert_eq!(acceptor.on_syn(&syn).unwrap().1, Some(restarted.isn()));
+    }
+
+    #[test]
+    fn test_mode_mismatch() {
+        let mut connector =
+            Connector::new(ArqMode::GoBackN, 255, SeqWidth::U8, ChecksumKind::default());
+        let mut acceptor = Acceptor::new(
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            ArqMode::SelectResend,
+            128,
+            SeqWidth::U8,
+            ChecksumKind::default(),
+        );
+
+        let (syn_ack, reset) = acceptor
+            .on_syn(&connector.syn().as_handshake().unwrap())
+            .unwrap();
+        assert_eq!(reset, None);
+        assert!(!acceptor.is_connected());
+        assert!(connector
+            .on_syn_ack(&syn_ack.as_handshake().unwrap())
+            .is_err());
+        assert!(!connector.is_established());
+    }
+}
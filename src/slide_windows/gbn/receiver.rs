@@ -2,48 +2,189 @@ use std::{io, net::SocketAddr};
 
 use crate::{
     fake_udp::UdpSocket,
-    packet::{ack::Ack, Packet},
+    packet::{
+        ack::Ack,
+        handshake::{ArqMode, Handshake},
+        nak::Nak,
+        seq::SeqWidth,
+        Packet, PacketDecodeError,
+    },
+    slide_windows::{config::TransportConfig, connect::Acceptor, DecodeErrorStats, Reassembler},
+    verify::ChecksumKind,
 };
 
 use super::GbnError;
 
 pub struct GoBackNReceiver {
     origin: SocketAddr,
-    last_ack: Ack,
-    pkg_id: u8,
+    /// 最近一次按序收到的序号
+    last_ack: u32,
+    pkg_id: u32,
+    /// 最近一次 NAK 的序号，同一个缺失序号只 NAK 一次
+    last_nak: Option<u32>,
+    seq_width: SeqWidth,
+    checksum: ChecksumKind,
+    /// 通告窗口的上限
+    window: u32,
+    reassembler: Reassembler,
+    acceptor: Acceptor,
 }
 
 impl GoBackNReceiver {
-    pub fn new(origin: SocketAddr) -> Self {
+    /// `config` 需要先通过 [`TransportConfig::validate`] 校验
+    pub fn new(origin: SocketAddr, config: &TransportConfig) -> Self {
+        let TransportConfig {
+            seq_width,
+            checksum,
+            ..
+        } = *config;
         Self {
             origin,
-            last_ack: Ack::new_ack(u8::MAX),
+            last_ack: seq_width.mask(),
             pkg_id: 0,
+            last_nak: None,
+            seq_width,
+            checksum,
+            window: config.window,
+            reassembler: Reassembler::new(config.max_message),
+            // 接收端只缓存一个 packet，窗口大小只用于限制发送端
+            acceptor: Acceptor::new(origin, ArqMode::GoBackN, config.window, seq_width, checksum),
         }
     }
 
+    /// 处理发送端的 SYN、ACK 与 Abort，新的连接会重置接收状态
+    pub async fn recv_handshake(
+        &mut self,
+        handshake: Handshake,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> Result<(), GbnError> {
+        let reset = self
+            .acceptor
+            .recv_handshake::<GbnError>(&handshake, buf, socket);
+        if let Some(isn) = reset.await? {
+            self.pkg_id = isn;
+            self.last_ack = self.seq_width.sub(isn, 1);
+            self.last_nak = None;
+            self.reassembler = Reassembler::new(self.reassembler.max_size());
+        }
+
+        Ok(())
+    }
+
+    /// 记录一次解码失败，返回原错误便于继续传递
+    pub fn record_decode_error(&mut self, err: PacketDecodeError) -> PacketDecodeError {
+        self.acceptor.record_decode_error(err)
+    }
+
+    pub fn decode_errors(&self) -> &DecodeErrorStats {
+        self.acceptor.decode_errors()
+    }
+
+    /// 接收一个 packet，分段消息在收到 End 分段后才返回完整消息
+    ///
+    /// `room` 为输出队列的剩余空间，为 0 时 ACK 通告零窗口
     pub async fn receive(
         &mut self,
         buf: &mut Vec<u8>,
         packet: Packet,
         socket: &UdpSocket,
-    ) -> Result<Vec<u8>, GbnError> {
-        let resp = if packet.get_id() == self.pkg_id {
-            self.last_ack = Ack::new_ack(self.pkg_id);
-            self.pkg_id = self.pkg_id.wrapping_add(1);
-            Ok(packet.get_body())
+        room: usize,
+    ) -> Result<Option<Vec<u8>>, GbnError> {
+        if !self.acceptor.is_connected() {
+            // 没有连接，要求发送端重新握手
+            self.acceptor
+                .send_reset(packet.get_id(), buf, socket)
+                .await?;
+            Err(GbnError::NotConnected)?
+        }
+        // 收到期望序号之后的 packet，说明中间有缺失
+        let gap = self.seq_width.before(self.pkg_id, packet.get_id());
+        let resp = if packet.seq_width() != self.seq_width {
+            Err(GbnError::SeqWidthMismatch {
+                expect: self.seq_width,
+                actual: packet.seq_width(),
+            })
+        } else if packet.get_id() == self.pkg_id {
+            self.last_ack = self.pkg_id;
+            self.pkg_id = self.seq_width.add(self.pkg_id, 1);
+            self.last_nak = None;
+            let split = packet.packet_split();
+            self.reassembler
+                .push(split, packet.get_body())
+                .map_err(GbnError::from)
         } else {
             Err(GbnError::PacketIdMisMatch)
         };
 
-        self.send_ack(buf, socket).await?;
+        // 本次完成的消息随后进入输出队列
+        let delivered = matches!(resp, Ok(Some(_))) as usize;
+        self.send_ack(buf, socket, room.saturating_sub(delivered))
+            .await?;
+        if resp.is_err() && gap {
+            self.send_nak(buf, socket).await?;
+        }
 
         resp
     }
 
-    pub async fn send_ack(&self, buf: &mut Vec<u8>, socket: &UdpSocket) -> io::Result<()> {
+    /// 处理 Leave，发送端的数据全部按序收到后确认并返回 true，接收端随后结束
+    pub async fn recv_leave(
+        &mut self,
+        leave_id: u32,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> Result<bool, GbnError> {
+        let expect = self.pkg_id;
+        Ok(self
+            .acceptor
+            .recv_leave(leave_id, expect, buf, socket)
+            .await?)
+    }
+
+    /// 回复发送端的 Ping
+    pub async fn send_pong(
+        &self,
+        ping_id: u32,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> io::Result<()> {
+        self.acceptor.send_pong(ping_id, buf, socket).await
+    }
+
+    /// 对当前期望的序号发送 NAK，要求发送端立即重传
+    pub async fn send_nak(&mut self, buf: &mut Vec<u8>, socket: &UdpSocket) -> io::Result<()> {
+        if self.last_nak == Some(self.pkg_id) {
+            return Ok(());
+        }
+        let nak = Nak::new_nak(self.pkg_id)
+            .with_seq_width(self.seq_width)
+            .with_checksum(self.checksum);
+
+        buf.clear();
+        let size = nak.write(buf)?;
+        socket.send_to(&buf[0..size], self.origin).await?;
+        println!("Sending Nak [{}] to Socket {}", self.pkg_id, self.origin);
+        self.last_nak = Some(self.pkg_id);
+
+        Ok(())
+    }
+
+    /// 确认最近一次按序收到的 packet 并通告接收窗口
+    ///
+    /// 通告的窗口不超过输出队列的剩余空间 `room`，输出队列已满时通告零窗口
+    pub async fn send_ack(
+        &self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        room: usize,
+    ) -> io::Result<()> {
+        let window = self.window.min(room.try_into().unwrap_or(u32::MAX));
+        let ack = Ack::new_window_ack(self.last_ack, window)
+            .with_seq_width(self.seq_width)
+            .with_checksum(self.checksum);
         buf.clear();
-        let size = self.last_ack.write(buf)?;
+        let size = ack.write(buf)?;
         let send_body = &buf[0..size];
 
         socket.send_to(send_body, self.origin).await?;
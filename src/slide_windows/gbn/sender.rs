@@ -1,77 +1,483 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 
 use crate::{
-    cycle_buffer::CycleBuffer,
+    cycle_buffer::{CbError, CycleBuffer},
     fake_udp::UdpSocket,
-    packet::Packet,
-    slide_windows::{Timer, TIMEOUT_MS},
+    packet::{
+        flags::{PackSplit, PacketType},
+        handshake::{ArqMode, Handshake, HandshakeStage},
+        Packet,
+    },
+    slide_windows::{
+        close::Closing,
+        config::TransportConfig,
+        congestion::{CongestionController, CongestionKind, Loss},
+        connect::{Connector, HandshakeError},
+        keepalive::Liveness,
+        retry::RetryPolicy,
+        rtt::RttEstimator,
+        segment_count, split_segments, Message, StatePacket, Timer,
+    },
+    verify::ChecksumKind,
 };
 
 use super::GbnError;
 
-const MAX_WINDOWS: u8 = 255;
-
 pub struct GoBackNSender {
     target: SocketAddr,
-    /// using 8bit for packet id
+    /// using k bit for packet id (see [`SeqWidth`])
     ///
-    /// max windows is 255
+    /// when packet id = 0 NAK => ACK 2 ^ k - 1
     ///
-    /// when packet id = 0 NAK => ACK 255
-    ///
-    /// but if the packet id = 255 is waiting too, thus
+    /// but if the packet id = 2 ^ k - 1 is waiting too, thus
     ///
     /// course confuse
     ///
-    /// max windows size is 255 = 2 ^ 8 - 1
-    /// 0~254
-    buffer: CycleBuffer<MAX_WINDOWS, Packet>,
+    /// max windows size is 2 ^ k - 1, 8bit is enough for 255
+    buffer: CycleBuffer<StatePacket>,
     /// timer
     timer: Option<Timer>,
+    /// 单个 packet body 的最大长度
+    max_segment: usize,
+    checksum: ChecksumKind,
+    connector: Connector,
+    /// 握手协商后的窗口大小
+    window: u32,
+    /// 对端最近一次通告的接收窗口
+    peer_window: u32,
+    congestion: CongestionKind,
+    controller: Box<dyn CongestionController>,
+    /// 触发快速重传的重复 ACK 数量，为 0 时关闭
+    dup_ack_threshold: u32,
+    /// 窗口起点没有前进时连续收到的重复 ACK 数量
+    dup_acks: u32,
+    /// 回退重传的进度，该序号及之后的 packet 等待拥塞窗口允许时重传
+    resend_from: Option<u32>,
+    /// 回退重传时的窗口终点，确认越过之前不再快速重传
+    recover: Option<u32>,
+    /// 等待窗口空余的消息
+    pending: VecDeque<Message>,
+    closing: Option<Closing>,
+    liveness: Liveness,
+    retry: RetryPolicy,
+    rtt: RttEstimator,
 }
 
 impl GoBackNSender {
-    pub fn new(target: SocketAddr) -> Self {
+    /// `config` 需要先通过 [`TransportConfig::validate`] 校验
+    pub fn new(target: SocketAddr, config: &TransportConfig) -> Self {
+        assert!(config.max_segment > 0, "max segment size must > 0");
         Self {
             target,
-            buffer: CycleBuffer::new(),
+            buffer: CycleBuffer::new(config.window, config.seq_width),
             timer: None,
+            max_segment: config.max_segment,
+            checksum: config.checksum,
+            connector: Connector::new(
+                ArqMode::GoBackN,
+                config.window,
+                config.seq_width,
+                config.checksum,
+            ),
+            window: config.window,
+            peer_window: config.window,
+            congestion: config.congestion,
+            controller: config.congestion.controller(config.window),
+            dup_ack_threshold: config.dup_ack_threshold,
+            dup_acks: 0,
+            resend_from: None,
+            recover: None,
+            pending: VecDeque::new(),
+            closing: None,
+            liveness: Liveness::new(config.keepalive),
+            retry: config.retry,
+            rtt: RttEstimator::new(config.initial_rto),
+        }
+    }
+
+    /// 当前的 RTT 估计
+    pub fn rtt(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
+    /// 收到对端的任意 packet
+    pub fn on_peer_packet(&mut self) {
+        self.liveness.on_recv();
+    }
+
+    /// 定期检查对端状态，空闲时发送 Ping，对端不可达时停止全部定时器
+    pub async fn keepalive(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> Result<(), GbnError> {
+        let ping = match self
+            .liveness
+            .tick(Instant::now(), self.target, self.buffer.width())
+        {
+            Ok(ping) => ping,
+            Err(err) => {
+                self.abort();
+                Err(err)?
+            }
+        };
+
+        if let Some(ping) = ping {
+            let ping = ping.with_checksum(self.checksum);
+            buf.clear();
+            let size = ping.write(buf)?;
+            socket.send_to(&buf[0..size], self.target).await?;
+            println!("Send Ping [{}]", ping.get_id());
+        }
+
+        Ok(())
+    }
+
+    /// 放弃连接，停止全部定时器并丢弃窗口内、发送队列中与握手期间缓存的消息
+    ///
+    /// 这些消息的送达通知随之返回 `Undelivered`；关闭流程无法完成，等待方同样收到通知失败
+    fn abort(&mut self) {
+        if let Some(t) = self.timer.take() {
+            t.stop();
+        }
+        self.connector.stop_timer();
+        self.connector.clear_pending();
+        self.buffer.reset(self.buffer.top());
+        self.pending.clear();
+        self.resend_from = None;
+        self.recover = None;
+        self.closing = None;
+    }
+
+    /// Reset 携带接收端拒绝的 packet 序号，只接受最近一个窗口内发出的序号，
+    /// 避免延迟到达或伪造的 Reset 中断当前连接
+    fn accepts_reset(&self, seq: u32) -> bool {
+        let distance = self.buffer.width().sub(self.buffer.top(), seq);
+        (1..=self.window).contains(&distance)
+    }
+
+    /// 开始关闭连接，窗口内的 packet 全部确认后发送 Leave，关闭完成时通知 `notify`
+    pub fn close(&mut self, notify: oneshot::Sender<()>) {
+        let mut closing = Closing::new(notify);
+        if !self.connector.is_established() && !self.connector.has_pending() {
+            // 连接尚未建立，没有需要等待的数据
+            closing.finish();
+        }
+        self.closing = Some(closing);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closing.as_ref().is_some_and(Closing::is_closed)
+    }
+
+    /// 关闭中、连接已建立且窗口已清空时发送 Leave
+    pub async fn poll_close(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), GbnError> {
+        // 连接建立前缓存的消息还没有发送
+        if !self.connector.is_established() {
+            return Ok(());
         }
+        let closing = match self.closing.as_mut() {
+            Some(closing)
+                if closing.need_leave() && self.buffer.is_empty() && self.pending.is_empty() =>
+            {
+                closing
+            }
+            _ => return Ok(()),
+        };
+        let leave = match closing.leave(self.buffer.top(), self.buffer.width(), self.checksum) {
+            Some(leave) => leave,
+            None => return Ok(()),
+        };
+
+        buf.clear();
+        let size = leave.write(buf)?;
+        socket.send_to(&buf[0..size], self.target).await?;
+        println!("Send Leave [{}]", leave.get_id());
+
+        let (timer, timeout) = Timer::start(self.rtt.rto());
+        closing.set_timer(timer);
+        timeout.need_resend_do(async move {
+            eprintln!("waiting Leave ACK timeout , resend");
+            timeout_send.send(()).await.ok();
+        });
+
+        Ok(())
     }
 
+    /// 发送 SYN 发起连接，超时后通过 `timeout_send` 触发重传
+    pub async fn connect(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), GbnError> {
+        let syn = self.connector.syn();
+        buf.clear();
+        let size = syn.write(buf)?;
+        socket.send_to(&buf[0..size], self.target).await?;
+        println!("Send SYN [{}]", self.connector.isn());
+
+        let (timer, timeout) = Timer::start(self.rtt.rto());
+        self.connector.set_timer(timer);
+        timeout.need_resend_do(async move {
+            eprintln!("waiting SYN-ACK timeout , resend");
+            timeout_send.send(()).await.ok();
+        });
+
+        Ok(())
+    }
+
+    /// 处理接收端回复的 SYN-ACK 与 Reset
+    pub async fn recv_handshake(
+        &mut self,
+        handshake: Handshake,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), GbnError> {
+        match handshake.stage {
+            HandshakeStage::SynAck => {
+                let established = match self.connector.on_syn_ack(&handshake) {
+                    // 对端仍保留着旧连接，要求其结束旧连接，之后重传的 SYN 即可建立新连接
+                    Err(HandshakeError::Challenged { current }) => {
+                        eprintln!("Peer still holds connection isn [{current}], abort it");
+                        let abort = self.connector.abort(current);
+                        buf.clear();
+                        let size = abort.write(buf)?;
+                        socket.send_to(&buf[0..size], self.target).await?;
+                        return Ok(());
+                    }
+                    result => result?,
+                };
+                let ack = self.connector.ack();
+                buf.clear();
+                let size = ack.write(buf)?;
+                socket.send_to(&buf[0..size], self.target).await?;
+
+                if let Some(established) = established {
+                    println!(
+                        "Connection established isn [{}] window [{}]",
+                        established.isn, established.window
+                    );
+                    self.buffer.reset(established.isn);
+                    self.window = established.window;
+                    self.peer_window = established.window;
+                    self.controller = self.congestion.controller(established.window);
+                    // 连接建立前缓存的消息，即使已经开始关闭也需要发送
+                    self.pending.extend(established.pending);
+                    self.poll_pending(buf, socket, timeout_send).await?;
+                }
+            }
+            // 接收端丢失了连接，窗口为空时重新握手，否则未确认的数据已经无法送达
+            HandshakeStage::Reset
+                if self.connector.is_established() && self.accepts_reset(handshake.isn) =>
+            {
+                let dropped = self.buffer.len();
+                if dropped > 0 {
+                    self.abort();
+                    Err(GbnError::ConnectionReset { dropped })?
+                }
+                if let Some(t) = self.timer.take() {
+                    t.stop();
+                }
+                eprintln!("Connection reset by peer, reconnect");
+                self.connector.restart();
+                self.connect(buf, socket, timeout_send).await?;
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// 发送一条消息，超过最大分段长度的消息会被切分为多个 packet
+    ///
+    /// 窗口剩余空间不足时先发送能够容纳的分段，其余分段在发送队列中等待
     pub async fn send(
+        &mut self,
+        buf: &mut Vec<u8>,
+        msg: Message,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), super::GbnError> {
+        if self.closing.is_some() {
+            Err(GbnError::Closed)?
+        }
+        if !self.connector.is_established() {
+            self.connector.queue(msg);
+            return Ok(());
+        }
+        self.pending.push_back(msg);
+        self.poll_pending(buf, socket, timeout_send).await
+    }
+
+    /// 按顺序发送窗口能够容纳的等待中的消息
+    pub async fn poll_pending(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), GbnError> {
+        if !self.connector.is_established() {
+            return Ok(());
+        }
+        loop {
+            let room = self.send_room() as usize;
+            let msg = match self.pending.front_mut() {
+                Some(msg) if room > 0 => msg,
+                _ => break,
+            };
+            if segment_count(msg.body.len(), self.max_segment) > room {
+                // 窗口只能容纳部分分段，其余分段与送达通知留在发送队列开头
+                let head = msg.split_head(room, self.max_segment);
+                for body in head.chunks(self.max_segment) {
+                    self.send_segment(
+                        buf,
+                        body.to_vec(),
+                        PackSplit::Follow,
+                        None,
+                        socket,
+                        timeout_send.clone(),
+                    )
+                    .await?;
+                }
+                break;
+            }
+            let msg = self.pending.pop_front().expect("pending message exists");
+            self.send_message(buf, msg, socket, timeout_send.clone())
+                .await?;
+        }
+        // 对端窗口为零，等待超时后发送窗口探测
+        if self.need_probe() && self.timer.is_none() {
+            self.start_timer(self.rtt.rto(), timeout_send);
+        }
+        Ok(())
+    }
+
+    /// 窗口、对端通告窗口与拥塞窗口允许继续发送的 packet 数量
+    fn send_room(&self) -> u32 {
+        self.peer_window
+            .min(self.controller.window())
+            .min(self.window)
+            .saturating_sub(self.buffer.len())
+    }
+
+    /// 对端通告零窗口且没有在途 packet 时，只能通过探测得知窗口重新打开
+    fn need_probe(&self) -> bool {
+        self.peer_window == 0 && self.buffer.is_empty() && !self.pending.is_empty()
+    }
+
+    fn start_timer(&mut self, timeout: Duration, timeout_send: mpsc::Sender<()>) {
+        let (timer, timeout) = Timer::start(timeout);
+        if let Some(t) = self.timer.replace(timer) {
+            t.stop();
+        }
+        timeout.need_resend_do(async move {
+            eprintln!("waiting timeout , resend");
+            timeout_send.send(()).await.ok();
+        });
+    }
+
+    /// 发送窗口探测，使用已确认的序号与空 body，接收端只会回复带有最新窗口的 ACK
+    async fn probe_window(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), GbnError> {
+        if !self.need_probe() {
+            self.timer = None;
+            return Ok(());
+        }
+        let probe_id = self.buffer.width().sub(self.buffer.top(), 1);
+        let probe = Packet::new_data(probe_id, Vec::new())
+            .with_seq_width(self.buffer.width())
+            .with_checksum(self.checksum);
+        buf.clear();
+        let size = probe.write(buf)?;
+        socket.send_to(&buf[0..size], self.target).await?;
+        println!("Send Window Probe [{probe_id}]");
+
+        self.start_timer(self.rtt.rto(), timeout_send);
+        Ok(())
+    }
+
+    async fn send_message(
+        &mut self,
+        buf: &mut Vec<u8>,
+        msg: Message,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), super::GbnError> {
+        let segments = split_segments(msg.body, self.max_segment);
+        if segments.len() > (self.window - self.buffer.len()) as usize {
+            Err(CbError::BufferFilled)?
+        }
+
+        // 送达通知跟随最后一个分段
+        let mut delivery = msg.delivery;
+        for (split, body) in segments {
+            let delivery = match split {
+                PackSplit::End => delivery.take(),
+                PackSplit::Follow => None,
+            };
+            self.send_segment(buf, body, split, delivery, socket, timeout_send.clone())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn send_segment(
         &mut self,
         buf: &mut Vec<u8>,
         body: Vec<u8>,
+        split: PackSplit,
+        delivery: Option<oneshot::Sender<()>>,
         socket: &UdpSocket,
         timeout_send: mpsc::Sender<()>,
     ) -> Result<(), super::GbnError> {
         // 封装包
-        let packet = Packet::new_data(self.buffer.top(), body);
+        let packet = Packet::new(self.buffer.top(), body, PacketType::Data, split)
+            .with_seq_width(self.buffer.width())
+            .with_checksum(self.checksum);
         buf.clear();
         let size = packet.write(buf)?;
         let send_packet = &buf[0..size];
 
         // set packet to buffer
-        self.buffer.push(packet)?;
+        self.buffer
+            .push(StatePacket::new_waiting(packet).with_delivery(delivery))?;
         println!("updated size: {}", self.buffer.len());
 
         // send packet
         let len = socket.send_to(send_packet, self.target).await?;
+        self.controller.on_send(Instant::now(), 1);
         println!(
             "Send Packet {} size {len}",
-            self.buffer.top().wrapping_sub(1)
+            self.buffer.width().sub(self.buffer.top(), 1)
         );
 
         // start timer
 
         // if this packet is the first, start a timer
         if self.buffer.len() == 1 {
-            let (timer, timeout) = Timer::start(Duration::from_millis(TIMEOUT_MS));
+            let (timer, timeout) = Timer::start(self.retry.backoff(self.rtt.rto(), 0));
             // stop last timer
-            self.timer.replace(timer).map(|t| t.stop());
+            if let Some(t) = self.timer.replace(timer) {
+                t.stop();
+            }
 
             // if time out send again
             timeout.need_resend_do(async move {
@@ -87,19 +493,56 @@ impl GoBackNSender {
     /// 在go back n 中， ack 是累计校验
     /// 即在缓冲区里面 packet id <= ack 的均为被收到且通过校验
     /// 接收端的缓冲区只有1
-    pub async fn recv_ack(&mut self, ack_num: u8, timeout_send: mpsc::Sender<()>) {
+    ///
+    /// `window` 为 ACK 携带的对端接收窗口
+    ///
+    /// 接收端每收到一个乱序的 packet 都会重复确认窗口起点之前的序号，
+    /// 连续收到足够多的重复 ACK 时不再等待超时，立即重传窗口内的全部 packet；
+    /// 通告窗口发生变化的 ACK 只是窗口更新，不算作重复 ACK
+    pub async fn recv_ack(
+        &mut self,
+        ack_num: u32,
+        window: Option<u32>,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), GbnError> {
+        let window_update = window.is_some_and(|window| window != self.peer_window);
+        if let Some(window) = window {
+            self.peer_window = window;
+        }
+        if let Some(closing) = self.closing.as_mut() {
+            closing.on_ack(ack_num);
+        }
+        // 以新确认的最后一个 packet 采样 RTT
+        let width = self.buffer.width();
+        let mut rtt = None;
+        if width.in_range(ack_num, self.buffer.button(), self.buffer.len()) {
+            rtt = self.buffer.get(ack_num).and_then(StatePacket::rtt_sample);
+            if let Some(rtt) = rtt {
+                self.rtt.on_sample(rtt);
+            }
+        }
+        if self.passes_recover(ack_num) {
+            self.recover = None;
+        }
         // ----end---ack_num-----------head
         // ack 在end 紧接着的上一个位置，那么就是NAK
-        match self.buffer.set_button(ack_num) {
+        match self.acknowledge(ack_num, rtt) {
             Ok(_) => {
                 println!("updated size: {}", self.buffer.len());
                 println!("ACK PASS");
+                self.dup_acks = 0;
+                self.poll_resend(buf, socket).await?;
 
-                if self.buffer.len() > 0 {
+                if !self.buffer.is_empty() {
                     //start a new timer
-                    let (timer, timeout) = Timer::start(Duration::from_millis(TIMEOUT_MS));
+                    // 收到新的确认说明链路恢复，超时时间不再退避
+                    let (timer, timeout) = Timer::start(self.retry.backoff(self.rtt.rto(), 0));
                     // stop old timer
-                    self.timer.replace(timer).map(|v| v.stop());
+                    if let Some(v) = self.timer.replace(timer) {
+                        v.stop();
+                    }
 
                     timeout.need_resend_do(async move {
                         eprintln!("waiting timeout , resend");
@@ -107,8 +550,18 @@ impl GoBackNSender {
                     });
 
                     tokio::task::yield_now().await;
-                } else {
-                    self.timer.take().map(|v| v.stop());
+                } else if let Some(v) = self.timer.take() {
+                    v.stop();
+                }
+            }
+            Err(_) if !window_update && self.is_dup_ack(ack_num) => {
+                self.dup_acks += 1;
+                println!(
+                    "Duplicate ACK [{ack_num}] {}/{}",
+                    self.dup_acks, self.dup_ack_threshold
+                );
+                if self.dup_acks == self.dup_ack_threshold {
+                    self.fast_retransmit(buf, socket, timeout_send).await?;
                 }
             }
             Err(_) => {
@@ -118,36 +571,185 @@ impl GoBackNSender {
                 )
             }
         }
+
+        Ok(())
     }
 
-    /// resend all packet in buffer that not recv ACK
-    pub async fn resend_all(
+    /// 有在途 packet 时重复确认窗口起点之前的序号，说明窗口起点的 packet 丢失
+    fn is_dup_ack(&self, ack_num: u32) -> bool {
+        self.dup_ack_threshold > 0
+            && !self.buffer.is_empty()
+            && ack_num == self.buffer.width().sub(self.buffer.button(), 1)
+    }
+
+    /// 确认覆盖了回退重传时窗口内的全部 packet
+    fn passes_recover(&self, ack_num: u32) -> bool {
+        let width = self.buffer.width();
+        let button = self.buffer.button();
+        self.recover.is_some_and(|recover| {
+            width.in_range(ack_num, button, self.buffer.len())
+                && width.sub(recover, button) <= width.sub(ack_num, button) + 1
+        })
+    }
+
+    /// 窗口起点的 packet 丢失时立即回退重传
+    ///
+    /// 同一次丢失会先后引起 NAK 与重复 ACK，确认越过上一次回退重传的窗口终点前只重传一次
+    async fn fast_retransmit(
         &mut self,
         buf: &mut Vec<u8>,
         socket: &UdpSocket,
         timeout_send: mpsc::Sender<()>,
     ) -> Result<(), GbnError> {
-        // stop old timer
-        self.timer.take().map(|v| v.stop());
+        self.dup_acks = 0;
+        if let Some(recover) = self.recover {
+            println!("Recovering until [{recover}], skip fast retransmit");
+            return Ok(());
+        }
+        println!("Fast retransmit from [{}]", self.buffer.button());
+        self.resend_all(Loss::Fast, buf, socket, timeout_send).await
+    }
+
+    /// 累计确认 `ack_num` 及之前的全部 packet，并通知已送达的消息与拥塞控制
+    fn acknowledge(&mut self, ack_num: u32, rtt: Option<Duration>) -> Result<(), CbError> {
+        let width = self.buffer.width();
+        let button = self.buffer.button();
+        if width.in_range(ack_num, button, self.buffer.len()) {
+            let acked = width.sub(ack_num, button) + 1;
+            for distance in 0..acked {
+                if let Some(packet) = self.buffer.get_mut(width.add(button, distance)) {
+                    packet.recv_ack();
+                }
+            }
+            self.controller.on_ack(Instant::now(), acked, rtt);
+        }
+        self.buffer.set_button(ack_num)
+    }
+
+    /// NAK 表示该序号之前的 packet 均已收到，该序号及之后的需要立即重传
+    pub async fn recv_nak(
+        &mut self,
+        nak_num: u32,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), GbnError> {
+        let width = self.buffer.width();
+        if !width.in_range(nak_num, self.buffer.button(), self.buffer.len()) {
+            println!("NAK num {nak_num} out of windows, ignore");
+            return Ok(());
+        }
+        if nak_num != self.buffer.button() {
+            let ack_num = width.sub(nak_num, 1);
+            if self.passes_recover(ack_num) {
+                self.recover = None;
+            }
+            self.acknowledge(ack_num, None)?;
+        }
+
+        self.fast_retransmit(buf, socket, timeout_send).await
+    }
+
+    /// 按拥塞窗口继续回退重传，已重传的 packet 计入在途数量
+    async fn poll_resend(&mut self, buf: &mut Vec<u8>, socket: &UdpSocket) -> Result<(), GbnError> {
+        let width = self.buffer.width();
+        while let Some(next) = self.resend_from {
+            let button = self.buffer.button();
+            if self.buffer.is_empty() {
+                self.resend_from = None;
+                break;
+            }
+            // 窗口起点越过重传进度时，之前的 packet 已被确认
+            let next = if width.in_range(next, button, self.buffer.len()) {
+                next
+            } else {
+                button
+            };
+            if width.sub(next, button) >= self.controller.window() {
+                self.resend_from = Some(next);
+                break;
+            }
 
-        // resend all data
-        let mut idx = self.buffer.button();
-        while idx != self.buffer.top() {
             // the packet is always exist
-            let packet = self.buffer.get(idx).unwrap();
+            let packet = self.buffer.get(next).unwrap();
             buf.clear();
-            let size = packet.write(buf)?;
-            let send_packet = &buf[0..size];
+            let size = packet.pkg.write(buf)?;
+            let len = socket.send_to(&buf[0..size], self.target).await?;
+            self.controller.on_send(Instant::now(), 1);
+            println!("Resend Packet {} size: [{}]", next, len);
 
-            let len = socket.send_to(send_packet, self.target).await?;
-            println!("Resend Packet {} size: [{}]", idx, len);
+            let next = width.add(next, 1);
+            self.resend_from = (next != self.buffer.top()).then_some(next);
+        }
+        Ok(())
+    }
 
-            // update idx
-            idx = idx.wrapping_add(1);
+    /// resend all packet in buffer that not recv ACK
+    ///
+    /// `loss` 为触发重传的原因，用于调整拥塞窗口；
+    /// 一次只重传拥塞窗口允许的数量，其余 packet 在收到新的确认后继续重传
+    pub async fn resend_all(
+        &mut self,
+        loss: Loss,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), GbnError> {
+        // 连接未建立时超时重传 SYN
+        if !self.connector.is_established() {
+            return self.connect(buf, socket, timeout_send).await;
+        }
+        // Leave 超时，由 poll_close 重传
+        if let Some(closing) = self.closing.as_mut() {
+            if closing.leave_seq().is_some() {
+                closing.on_timeout();
+                return Ok(());
+            }
+        }
+        // 没有在途 packet，超时来自窗口探测
+        if self.buffer.is_empty() {
+            return self.probe_window(buf, socket, timeout_send).await;
+        }
+        // stop old timer
+        if let Some(v) = self.timer.take() {
+            v.stop();
+        }
+        // 只统计窗口内最早 packet 的超时次数，其余 packet 只是被连带重传；
+        // 快速重传说明链路仍然可达，不计入重传次数
+        let retries = match (self.buffer.get_mut(self.buffer.button()), loss) {
+            (Some(first), Loss::Timeout) => first.retry(),
+            (Some(first), Loss::Fast) => first.retries,
+            (None, _) => return Ok(()),
+        };
+        if self.retry.is_exhausted(retries) {
+            let packet_id = self.buffer.button();
+            self.abort();
+            Err(GbnError::RetriesExhausted {
+                packet_id,
+                retries: retries - 1,
+            })?
+        }
+        // 接收端丢弃乱序的 packet，窗口内的 packet 全部视为丢失
+        let in_flight = self.buffer.len();
+        self.controller
+            .on_loss(Instant::now(), loss, in_flight, in_flight);
+
+        // 连带重传的 packet 同样不再参与 RTT 采样
+        let width = self.buffer.width();
+        for distance in 0..in_flight {
+            if let Some(packet) = self
+                .buffer
+                .get_mut(width.add(self.buffer.button(), distance))
+            {
+                packet.sent_at = None;
+            }
         }
+        self.resend_from = Some(self.buffer.button());
+        self.recover = Some(self.buffer.top());
+        self.poll_resend(buf, socket).await?;
 
         // create new timer
-        let (timer, timeout) = Timer::start(Duration::from_millis(TIMEOUT_MS));
+        let (timer, timeout) = Timer::start(self.retry.backoff(self.rtt.rto(), retries));
         self.timer = Some(timer);
 
         timeout.need_resend_do(async move {
@@ -160,3 +762,261 @@ impl GoBackNSender {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use tokio::sync::mpsc;
+
+    use crate::{
+        fake_udp::UdpSocket,
+        packet::handshake::{ArqMode, Handshake, HandshakeStage},
+        slide_windows::{
+            config::TransportConfig,
+            congestion::{CongestionController, Loss, LOSS_WINDOW},
+            gbn::GbnError,
+            retry::RetryPolicy,
+            Message,
+        },
+    };
+
+    use super::GoBackNSender;
+
+    /// 完成握手的发送端，packet 发往本端 socket 且不会被读取，测试期间定时器不会触发
+    async fn established(config: TransportConfig) -> (GoBackNSender, UdpSocket) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = TransportConfig {
+            initial_rto: Duration::from_secs(60),
+            ..config
+        };
+        let mut sender = GoBackNSender::new(socket.local_addr().unwrap(), &config);
+        let syn_ack = Handshake {
+            stage: HandshakeStage::SynAck,
+            isn: sender.connector.isn(),
+            mode: ArqMode::GoBackN,
+            window: config.window,
+        };
+        let (timeout_send, _) = mpsc::channel(1);
+        sender
+            .recv_handshake(syn_ack, &mut Vec::new(), &socket, timeout_send)
+            .await
+            .unwrap();
+        (sender, socket)
+    }
+
+    async fn ack(
+        sender: &mut GoBackNSender,
+        socket: &UdpSocket,
+        ack_num: u32,
+        window: Option<u32>,
+    ) {
+        let (timeout_send, _) = mpsc::channel(1);
+        sender
+            .recv_ack(ack_num, window, &mut Vec::new(), socket, timeout_send)
+            .await
+            .unwrap();
+    }
+
+    async fn send_many(sender: &mut GoBackNSender, socket: &UdpSocket, count: u8) {
+        let (timeout_send, _) = mpsc::channel(1);
+        for body in 0..count {
+            let msg = Message::new(vec![body]);
+            sender
+                .send(&mut Vec::new(), msg, socket, timeout_send.clone())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resend_within_cwnd() {
+        let config = TransportConfig {
+            window: 8,
+            ..Default::default()
+        };
+        let (mut sender, socket) = established(config).await;
+        let (timeout_send, _) = mpsc::channel(1);
+        let buf = &mut Vec::new();
+        let width = sender.buffer.width();
+        let isn = sender.buffer.button();
+        for body in 0..4 {
+            let msg = Message::new(vec![body]);
+            sender
+                .send(buf, msg, &socket, timeout_send.clone())
+                .await
+                .unwrap();
+        }
+
+        // 超时后拥塞窗口只允许一个 packet 在途，其余 packet 等待新的确认
+        sender
+            .resend_all(Loss::Timeout, buf, &socket, timeout_send.clone())
+            .await
+            .unwrap();
+        assert_eq!(sender.controller.window(), LOSS_WINDOW);
+        assert_eq!(sender.resend_from, Some(width.add(isn, 1)));
+
+        // 每个新的确认按增长后的拥塞窗口继续重传
+        sender
+            .recv_ack(isn, None, buf, &socket, timeout_send.clone())
+            .await
+            .unwrap();
+        assert_eq!(sender.resend_from, Some(width.add(isn, 3)));
+        sender
+            .recv_ack(width.add(isn, 2), None, buf, &socket, timeout_send)
+            .await
+            .unwrap();
+        assert_eq!(sender.resend_from, None);
+    }
+
+    /// 窗口固定的拥塞控制，记录回退重传的次数
+    struct CountLoss(Arc<AtomicU32>);
+
+    impl CongestionController for CountLoss {
+        fn window(&self) -> u32 {
+            u32::MAX
+        }
+
+        fn on_ack(&mut self, _now: Instant, _acked: u32, _rtt: Option<Duration>) {}
+
+        fn on_loss(&mut self, _now: Instant, _loss: Loss, _in_flight: u32, _lost: u32) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fast_retransmit_once() {
+        let config = TransportConfig {
+            window: 8,
+            dup_ack_threshold: 3,
+            ..Default::default()
+        };
+        let (mut sender, socket) = established(config).await;
+        let losses = Arc::new(AtomicU32::new(0));
+        sender.controller = Box::new(CountLoss(losses.clone()));
+        let width = sender.buffer.width();
+        let isn = sender.buffer.button();
+        send_many(&mut sender, &socket, 4).await;
+        let dup = width.sub(isn, 1);
+
+        // 只更新通告窗口的 ACK 不算作重复 ACK
+        for window in [7, 6, 5] {
+            ack(&mut sender, &socket, dup, Some(window)).await;
+        }
+        assert_eq!(losses.load(Ordering::Relaxed), 0);
+        // 达到阈值时只回退重传一次
+        for _ in 0..3 {
+            ack(&mut sender, &socket, dup, None).await;
+        }
+        assert_eq!(losses.load(Ordering::Relaxed), 1);
+
+        // 同一次丢失随后的 NAK 与重复 ACK 不再重传
+        let (timeout_send, _) = mpsc::channel(1);
+        sender
+            .recv_nak(isn, &mut Vec::new(), &socket, timeout_send)
+            .await
+            .unwrap();
+        for _ in 0..6 {
+            ack(&mut sender, &socket, dup, None).await;
+        }
+        assert_eq!(losses.load(Ordering::Relaxed), 1);
+
+        // 确认越过回退重传时的窗口终点后，新的丢失再次触发
+        let last = width.add(isn, 3);
+        ack(&mut sender, &socket, last, None).await;
+        send_many(&mut sender, &socket, 4).await;
+        for _ in 0..3 {
+            ack(&mut sender, &socket, last, None).await;
+        }
+        assert_eq!(losses.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reset_in_window() {
+        let (mut sender, socket) = established(TransportConfig::default()).await;
+        send_many(&mut sender, &socket, 2).await;
+        let width = sender.buffer.width();
+        let isn = sender.buffer.button();
+        let mut reset = Handshake {
+            stage: HandshakeStage::Reset,
+            isn: width.add(isn, 2),
+            mode: ArqMode::GoBackN,
+            window: 0,
+        };
+        let (timeout_send, _) = mpsc::channel(1);
+
+        // 序号不在最近发出的窗口内的 Reset 被忽略
+        sender
+            .recv_handshake(
+                reset.clone(),
+                &mut Vec::new(),
+                &socket,
+                timeout_send.clone(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(sender.buffer.len(), 2);
+
+        reset.isn = width.add(isn, 1);
+        assert!(matches!(
+            sender
+                .recv_handshake(reset, &mut Vec::new(), &socket, timeout_send)
+                .await,
+            Err(GbnError::ConnectionReset { dropped: 2 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retry_exhausted() {
+        let config = TransportConfig {
+            window: 2,
+            retry: RetryPolicy {
+                max_retries: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (mut sender, socket) = established(config).await;
+        let (timeout_send, _) = mpsc::channel(1);
+        let buf = &mut Vec::new();
+        let mut deliveries = Vec::new();
+        for body in 0..3 {
+            let (msg, delivery) = Message::with_delivery(vec![body]);
+            sender
+                .send(buf, msg, &socket, timeout_send.clone())
+                .await
+                .unwrap();
+            deliveries.push(delivery);
+        }
+        // 窗口已满，最后一条消息在发送队列中等待
+        assert_eq!(sender.pending.len(), 1);
+
+        // 快速重传不计入重传次数
+        for loss in [Loss::Fast, Loss::Fast, Loss::Timeout] {
+            sender
+                .resend_all(loss, buf, &socket, timeout_send.clone())
+                .await
+                .unwrap();
+        }
+
+        // 超时次数用尽时窗口与发送队列中的消息一起丢弃
+        assert!(matches!(
+            sender
+                .resend_all(Loss::Timeout, buf, &socket, timeout_send)
+                .await,
+            Err(GbnError::RetriesExhausted { retries: 1, .. })
+        ));
+        assert!(sender.buffer.is_empty());
+        assert!(sender.pending.is_empty());
+        assert!(sender.resend_from.is_none() && sender.recover.is_none());
+        for delivery in deliveries {
+            assert!(delivery.await.is_err());
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! 对端存活检测
+//!
+//! 发送端在一段时间内没有收到对端的任何 packet 时发送 Ping，
+//! 超过阈值仍没有回应则认为对端不可达，停止全部定时器并通知应用层
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::packet::{seq::SeqWidth, Packet};
+
+#[derive(Debug, Clone, Copy)]
+pub struct KeepAliveConfig {
+    /// 多久没有收到对端的 packet 后发送 Ping
+    pub interval: Duration,
+    /// 多久没有收到对端的 packet 后认为对端不可达
+    pub dead_after: Duration,
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            dead_after: Duration::from_secs(30),
+        }
+    }
+}
+
+impl KeepAliveConfig {
+    /// 检查间隔，取 Ping 间隔的一半
+    pub fn tick_period(&self) -> Duration {
+        self.interval / 2
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("对端 {peer} 已 {silent:?} 没有响应")]
+pub struct PeerUnreachable {
+    pub peer: SocketAddr,
+    pub silent: Duration,
+}
+
+pub struct Liveness {
+    config: KeepAliveConfig,
+    last_recv: Instant,
+    ping_id: u32,
+}
+
+impl Liveness {
+    pub fn new(config: KeepAliveConfig) -> Self {
+        Self {
+            config,
+            last_recv: Instant::now(),
+            ping_id: 0,
+        }
+    }
+
+    /// 收到对端的任意 packet
+    pub fn on_recv(&mut self) {
+        self.last_recv = Instant::now();
+    }
+
+    /// 在 `now` 时检查对端状态，需要发送 Ping 时返回 Ping packet
+    pub fn tick(
+        &mut self,
+        now: Instant,
+        peer: SocketAddr,
+        seq_width: SeqWidth,
+    ) -> Result<Option<Packet>, PeerUnreachable> {
+        let silent = now.saturating_duration_since(self.last_recv);
+        if silent >= self.config.dead_after {
+            Err(PeerUnreachable { peer, silent })
+        } else if silent >= self.config.interval {
+            self.ping_id = seq_width.add(self.ping_id, 1);
+            Ok(Some(
+                Packet::new_ping(self.ping_id).with_seq_width(seq_width),
+            ))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::packet::{keepalive::KeepAlive, seq::SeqWidth};
+
+    use super::{KeepAliveConfig, Liveness};
+
+    #[test]
+    fn test_liveness() {
+        let peer = "127.0.0.1:9000".parse().unwrap();
+        let mut liveness = Liveness::new(KeepAliveConfig {
+            interval: Duration::from_secs(1),
+            dead_after: Duration::from_secs(3),
+        });
+        let start = Instant::now();
+
+        assert!(liveness.tick(start, peer, SeqWidth::U8).unwrap().is_none());
+
+        let ping = liveness
+            .tick(start + Duration::from_secs(2), peer, SeqWidth::U8)
+            .unwrap()
+            .unwrap();
+        assert_eq!(ping.as_keepalive(), Some(KeepAlive::Ping));
+
+        let err = liveness
+            .tick(start + Duration::from_secs(4), peer, SeqWidth::U8)
+            .unwrap_err();
+        assert_eq!(err.peer, peer);
+
+        // 收到回应后重新计时
+        liveness.on_recv();
+        assert!(liveness
+            .tick(Instant::now(), peer, SeqWidth::U8)
+            .unwrap()
+            .is_none());
+    }
+}
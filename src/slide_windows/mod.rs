@@ -1,16 +1,140 @@
 //! 滑动窗口
 
-use std::time::Duration;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
 
 use futures::Future;
-use tokio::{sync::oneshot, task::JoinHandle};
+use tokio::{
+    sync::{oneshot, OwnedSemaphorePermit},
+    task::JoinHandle,
+};
 
-use crate::packet::Packet;
+use crate::packet::{flags::PackSplit, Packet, PacketDecodeError};
+pub mod close;
+pub mod config;
+pub mod congestion;
+pub mod connect;
 pub mod gbn;
+pub mod keepalive;
+pub mod output;
+mod reassemble;
+pub mod retry;
+pub mod rtt;
+pub mod saw;
 pub mod sr;
+pub mod stream;
+pub mod transport;
+
+pub use reassemble::{MessageTooLarge, Reassembler};
 
 pub const MAX_BUFF_SIZE: usize = 1024 * 1024 * 4 + 32;
-pub const TIMEOUT_MS: u64 = 5000;
+/// 尚未测得 RTT 时的初始重传超时
+pub const TIMEOUT_MS: u64 = 1000;
+/// 默认最大分段长度，单个 packet 的 body 不超过该值
+pub const DEFAULT_MAX_SEGMENT: usize = 1024;
+/// 默认重组消息长度上限
+pub const DEFAULT_MAX_MESSAGE: usize = 1024 * 1024 * 4;
+/// 默认发送队列长度
+pub const DEFAULT_MAX_PENDING: usize = 64;
+/// 默认触发快速重传的重复 ACK 数量
+pub const DEFAULT_DUP_ACK_THRESHOLD: u32 = 3;
+
+/// 将应用层消息按照最大分段长度切分
+///
+/// 除最后一段为 [`PackSplit::End`] 外其余均为 [`PackSplit::Follow`]，
+/// 空消息也会生成一个空的 End 分段
+pub fn split_segments(body: Vec<u8>, max_segment: usize) -> Vec<(PackSplit, Vec<u8>)> {
+    if body.len() <= max_segment {
+        return vec![(PackSplit::End, body)];
+    }
+
+    let mut segments = body
+        .chunks(max_segment)
+        .map(|seg| (PackSplit::Follow, seg.to_vec()))
+        .collect::<Vec<_>>();
+    if let Some((split, _)) = segments.last_mut() {
+        *split = PackSplit::End;
+    }
+    segments
+}
+/// 应用层的一条消息
+#[derive(Debug)]
+pub struct Message {
+    pub body: Vec<u8>,
+    /// 全部分段确认后通知，消息被丢弃时随之丢弃
+    pub delivery: Option<oneshot::Sender<()>>,
+    /// 占用的发送队列名额，消息进入窗口后释放
+    pub permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Message {
+    /// 不需要送达通知的消息
+    pub fn new(body: Vec<u8>) -> Self {
+        Self {
+            body,
+            delivery: None,
+            permit: None,
+        }
+    }
+
+    /// 需要送达通知的消息
+    pub fn with_delivery(body: Vec<u8>) -> (Self, Delivery) {
+        let (notify, wait) = oneshot::channel();
+        let msg = Self {
+            delivery: Some(notify),
+            ..Self::new(body)
+        };
+        (msg, Delivery(wait))
+    }
+
+    pub fn with_permit(mut self, permit: OwnedSemaphorePermit) -> Self {
+        self.permit = Some(permit);
+        self
+    }
+
+    /// 取出前 `segments` 个分段的内容，其余部分连同送达通知与发送队列名额留在消息中
+    pub fn split_head(&mut self, segments: usize, max_segment: usize) -> Vec<u8> {
+        let at = (segments * max_segment).min(self.body.len());
+        let rest = self.body.split_off(at);
+        std::mem::replace(&mut self.body, rest)
+    }
+}
+
+impl From<Vec<u8>> for Message {
+    fn from(body: Vec<u8>) -> Self {
+        Self::new(body)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("消息在确认前被丢弃")]
+pub struct Undelivered;
+
+/// 消息的送达通知，消息的全部分段确认后完成
+///
+/// 消息因连接关闭、重置、重传次数用尽或缓冲区已满被丢弃时返回 [`Undelivered`]
+#[derive(Debug)]
+pub struct Delivery(oneshot::Receiver<()>);
+
+impl Future for Delivery {
+    type Output = Result<(), Undelivered>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx).map_err(|_| Undelivered)
+    }
+}
+
+/// 消息按照最大分段长度切分后的分段数量
+pub fn segment_count(len: usize, max_segment: usize) -> usize {
+    len.div_ceil(max_segment).max(1)
+}
+
+/// 交给接收端处理的 packet，解码失败的 packet 由接收端记录
+pub struct RecvMsg(pub Result<Packet, PacketDecodeError>);
+
 /// 定时器，用于超时重传
 pub struct Timer {
     handle: JoinHandle<()>,
@@ -95,6 +219,55 @@ impl Drop for Timer {
     }
 }
 
+/// 各类 packet 解码失败的计数
+#[derive(Debug, Default, Clone)]
+pub struct DecodeErrorStats {
+    pub bad_checksum: u64,
+    pub bad_magic: u64,
+    pub unsupported_version: u64,
+    pub unknown_type: u64,
+    pub unknown_split: u64,
+    pub unknown_size_class: u64,
+    pub unknown_seq_width: u64,
+    pub unknown_checksum: u64,
+    pub truncated_body: u64,
+    pub trailing_bytes: u64,
+    pub length_mismatch: u64,
+}
+
+impl DecodeErrorStats {
+    pub fn record(&mut self, err: &PacketDecodeError) {
+        let counter = match err {
+            PacketDecodeError::BadChecksum => &mut self.bad_checksum,
+            PacketDecodeError::BadMagic(_) => &mut self.bad_magic,
+            PacketDecodeError::UnsupportedVersion(_) => &mut self.unsupported_version,
+            PacketDecodeError::UnknownType(_) => &mut self.unknown_type,
+            PacketDecodeError::UnknownSplit(_) => &mut self.unknown_split,
+            PacketDecodeError::UnknownSizeClass(_) => &mut self.unknown_size_class,
+            PacketDecodeError::UnknownSeqWidth(_) => &mut self.unknown_seq_width,
+            PacketDecodeError::UnknownChecksum(_) => &mut self.unknown_checksum,
+            PacketDecodeError::TruncatedBody { .. } => &mut self.truncated_body,
+            PacketDecodeError::TrailingBytes(_) => &mut self.trailing_bytes,
+            PacketDecodeError::LengthMismatch { .. } => &mut self.length_mismatch,
+        };
+        *counter += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.bad_checksum
+            + self.bad_magic
+            + self.unsupported_version
+            + self.unknown_type
+            + self.unknown_split
+            + self.unknown_size_class
+            + self.unknown_seq_width
+            + self.unknown_checksum
+            + self.truncated_body
+            + self.trailing_bytes
+            + self.length_mismatch
+    }
+}
+
 #[derive(Debug, Default)]
 pub enum State {
     #[default]
@@ -106,6 +279,12 @@ pub enum State {
 pub struct StatePacket {
     pub state: State,
     pub pkg: Packet,
+    /// 已重传的次数
+    pub retries: u32,
+    /// 首次发送的时间，重传后置空，不再用于 RTT 采样
+    pub sent_at: Option<Instant>,
+    /// 消息最后一个分段确认时的送达通知
+    pub delivery: Option<oneshot::Sender<()>>,
 }
 
 impl StatePacket {
@@ -113,14 +292,87 @@ impl StatePacket {
         Self {
             state: State::WaitingAck,
             pkg,
+            retries: 0,
+            sent_at: Some(Instant::now()),
+            delivery: None,
         }
     }
 
+    pub fn with_delivery(mut self, delivery: Option<oneshot::Sender<()>>) -> Self {
+        self.delivery = delivery;
+        self
+    }
+
+    /// 记录一次重传，返回累计的重传次数
+    pub fn retry(&mut self) -> u32 {
+        self.sent_at = None;
+        self.retries += 1;
+        self.retries
+    }
+
+    /// 从首次发送到现在的时间，重传过的 packet 没有有效样本
+    pub fn rtt_sample(&self) -> Option<Duration> {
+        self.sent_at.map(|sent_at| sent_at.elapsed())
+    }
+
     pub fn recv_ack(&mut self) {
-        self.state = State::Done
+        self.state = State::Done;
+        if let Some(delivery) = self.delivery.take() {
+            delivery.send(()).ok();
+        }
     }
 
     pub fn is_down(&self) -> bool {
         matches!(self.state, State::Done)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::packet::{flags::PackSplit, Packet};
+
+    use super::{segment_count, split_segments, Message, StatePacket};
+
+    #[test]
+    fn test_split_segments() {
+        // small msg only one End packet
+        let segments = split_segments(vec![1, 2, 3], 4);
+        assert_eq!(segments.len(), 1);
+        assert!(matches!(segments[0], (PackSplit::End, ref b) if b == &[1, 2, 3]));
+
+        // empty msg still one packet
+        let segments = split_segments(Vec::new(), 4);
+        assert_eq!(segments.len(), 1);
+        assert!(matches!(segments[0], (PackSplit::End, ref b) if b.is_empty()));
+
+        // large msg split into Follow ... End
+        let segments = split_segments((0..10).collect(), 4);
+        let splits = segments
+            .iter()
+            .map(|(split, body)| (matches!(split, PackSplit::End), body.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(splits, [(false, 4), (false, 4), (true, 2)]);
+
+        for len in [0, 3, 4, 5, 10, 12] {
+            assert_eq!(segment_count(len, 4), split_segments(vec![0; len], 4).len());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delivery() {
+        // 确认后送达
+        let (msg, delivery) = Message::with_delivery(vec![1]);
+        let mut packet =
+            StatePacket::new_waiting(Packet::new_data(0, msg.body)).with_delivery(msg.delivery);
+        packet.recv_ack();
+        assert!(packet.is_down());
+        assert!(delivery.await.is_ok());
+
+        // 确认前被丢弃
+        let (msg, delivery) = Message::with_delivery(vec![1]);
+        let packet =
+            StatePacket::new_waiting(Packet::new_data(0, msg.body)).with_delivery(msg.delivery);
+        drop(packet);
+        assert!(delivery.await.is_err());
+    }
+}
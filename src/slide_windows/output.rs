@@ -0,0 +1,132 @@
+//! 接收端的输出队列
+//!
+//! 应用层读取较慢时输出队列会被填满，接收端把重组完成的消息暂存在本地而不是等待，
+//! 从而继续处理后续的 packet、窗口探测与 Ping，同时向发送端通告零窗口。
+//! 暂存的消息达到上限后接收端丢弃新的数据 packet，由发送端在窗口重新打开后重传
+
+use std::collections::VecDeque;
+
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+pub struct Output {
+    sender: mpsc::Sender<Vec<u8>>,
+    /// 输出队列已满时暂存的消息
+    backlog: VecDeque<Vec<u8>>,
+    /// 暂存消息数量的上限，通常为一个窗口
+    limit: usize,
+}
+
+impl Output {
+    pub fn new(sender: mpsc::Sender<Vec<u8>>, limit: usize) -> Self {
+        Self {
+            sender,
+            backlog: VecDeque::new(),
+            limit,
+        }
+    }
+
+    /// 输出队列的剩余空间，有暂存的消息时为 0
+    pub fn room(&self) -> usize {
+        if self.backlog.is_empty() {
+            self.sender.capacity()
+        } else {
+            0
+        }
+    }
+
+    /// 是否有消息在等待输出队列的空位
+    pub fn is_blocked(&self) -> bool {
+        !self.backlog.is_empty()
+    }
+
+    /// 暂存的消息达到上限，接收端不再接收新的数据 packet
+    pub fn is_full(&self) -> bool {
+        self.backlog.len() >= self.limit
+    }
+
+    /// 按顺序交付消息，输出队列已满时暂存
+    pub fn push(&mut self, msg: Vec<u8>) {
+        self.backlog.push_back(msg);
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        while let Some(msg) = self.backlog.pop_front() {
+            match self.sender.try_send(msg) {
+                Ok(()) => (),
+                Err(TrySendError::Full(msg)) => {
+                    self.backlog.push_front(msg);
+                    break;
+                }
+                // 应用层不再读取，暂存的消息没有意义
+                Err(TrySendError::Closed(_)) => self.backlog.clear(),
+            }
+        }
+    }
+
+    /// 等待输出队列出现空位后交付暂存的消息
+    pub async fn drain(&mut self) {
+        match self.sender.reserve().await {
+            Ok(permit) => {
+                if let Some(msg) = self.backlog.pop_front() {
+                    permit.send(msg);
+                }
+            }
+            Err(_) => self.backlog.clear(),
+        }
+        self.flush();
+    }
+
+    /// 接收端结束前交付全部暂存的消息
+    pub async fn finish(mut self) {
+        while let Some(msg) = self.backlog.pop_front() {
+            if self.sender.send(msg).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::sync::mpsc;
+
+    use super::Output;
+
+    #[tokio::test]
+    async fn test_backlog() {
+        let (sender, mut receiver) = mpsc::channel(2);
+        let mut output = Output::new(sender, 2);
+        assert_eq!(output.room(), 2);
+
+        // 输出队列已满时暂存，不会阻塞
+        for msg in 0..4 {
+            output.push(vec![msg]);
+        }
+        assert!(output.is_blocked());
+        assert!(output.is_full());
+        assert_eq!(output.room(), 0);
+
+        // 读取后按顺序交付暂存的消息
+        assert_eq!(receiver.recv().await.unwrap(), [0]);
+        output.drain().await;
+        assert!(output.is_blocked());
+        assert!(!output.is_full());
+        assert_eq!(receiver.recv().await.unwrap(), [1]);
+        assert_eq!(receiver.recv().await.unwrap(), [2]);
+        output.drain().await;
+        assert!(!output.is_blocked());
+        assert_eq!(output.room(), 1);
+
+        output.push(vec![4]);
+        output.push(vec![5]);
+        assert!(output.is_blocked());
+        // 结束时等待全部暂存的消息被读取
+        let finish = tokio::spawn(output.finish());
+        for msg in [3, 4, 5] {
+            assert_eq!(receiver.recv().await.unwrap(), [msg]);
+        }
+        finish.await.unwrap();
+        assert!(receiver.recv().await.is_none());
+    }
+}
@@ -0,0 +1,110 @@
+//! 分段消息重组
+//!
+//! 将连续的 [`PackSplit::Follow`] 分段与最后的 [`PackSplit::End`] 分段拼接为完整消息
+
+use crate::packet::flags::PackSplit;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+#[error("消息长度超过重组上限 {limit} byte，丢弃该消息")]
+pub struct MessageTooLarge {
+    pub limit: usize,
+}
+
+#[derive(Debug)]
+pub struct Reassembler {
+    local_buf: Vec<u8>,
+    /// 重组缓冲区上限
+    max_size: usize,
+    /// 当前消息已超过上限，丢弃后续分段直到 End
+    discarding: bool,
+}
+
+impl Reassembler {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            local_buf: Vec::new(),
+            max_size,
+            discarding: false,
+        }
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// 按序加入一个分段，收到 End 分段时返回完整消息
+    ///
+    /// 消息超过上限时只在第一次超限时返回错误，之后的分段被静默丢弃
+    pub fn push(
+        &mut self,
+        split: PackSplit,
+        body: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, MessageTooLarge> {
+        if self.discarding {
+            self.discarding = matches!(split, PackSplit::Follow);
+            return Ok(None);
+        }
+
+        if self.local_buf.len() + body.len() > self.max_size {
+            self.local_buf = Vec::new();
+            self.discarding = matches!(split, PackSplit::Follow);
+            Err(MessageTooLarge {
+                limit: self.max_size,
+            })?
+        }
+
+        // 未分段的消息直接返回，避免再复制一次
+        if self.local_buf.is_empty() && matches!(split, PackSplit::End) {
+            return Ok(Some(body));
+        }
+
+        self.local_buf.extend(body);
+        match split {
+            PackSplit::End => Ok(Some(std::mem::take(&mut self.local_buf))),
+            PackSplit::Follow => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::packet::flags::PackSplit;
+
+    use super::{MessageTooLarge, Reassembler};
+
+    #[test]
+    fn test_reassemble() {
+        let mut reassembler = Reassembler::new(8);
+
+        assert_eq!(reassembler.push(PackSplit::Follow, vec![1, 2]), Ok(None));
+        assert_eq!(reassembler.push(PackSplit::Follow, vec![3]), Ok(None));
+        assert_eq!(
+            reassembler.push(PackSplit::End, vec![4]),
+            Ok(Some(vec![1, 2, 3, 4]))
+        );
+
+        // single End packet
+        assert_eq!(reassembler.push(PackSplit::End, vec![5]), Ok(Some(vec![5])));
+    }
+
+    #[test]
+    fn test_over_limit() {
+        let mut reassembler = Reassembler::new(4);
+
+        assert_eq!(reassembler.push(PackSplit::Follow, vec![0; 3]), Ok(None));
+        // over limit, report once
+        assert_eq!(
+            reassembler.push(PackSplit::Follow, vec![0; 3]),
+            Err(MessageTooLarge { limit: 4 })
+        );
+        // rest of the msg drop silently
+        assert_eq!(reassembler.push(PackSplit::Follow, vec![0]), Ok(None));
+        assert_eq!(reassembler.push(PackSplit::End, vec![0]), Ok(None));
+
+        // next msg work again
+        assert_eq!(
+            reassembler.push(PackSplit::End, vec![1, 2]),
+            Ok(Some(vec![1, 2]))
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! 重传次数限制与指数退避
+
+use std::time::Duration;
+
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// 退避后超时时间的上限
+    pub max_backoff: Duration,
+    /// 单个 packet 最多超时重传的次数，超过后发送失败；NAK 等触发的快速重传不计入
+    pub max_retries: u32,
+    /// 抖动比例，取值 `[0, 1)`，超时时间在 `[1 - jitter, 1 + jitter]` 倍之间随机
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_backoff: Duration::from_secs(60),
+            max_retries: 8,
+            jitter: 0.1,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 已经重传 `retries` 次后的超时时间，`rto` 为当前估计的重传超时
+    pub fn backoff(&self, rto: Duration, retries: u32) -> Duration {
+        self.backoff_with(rto, retries, rand::thread_rng().gen_range(-1.0..=1.0))
+    }
+
+    /// `unit` 为 `[-1, 1]` 内的随机数
+    fn backoff_with(&self, rto: Duration, retries: u32, unit: f64) -> Duration {
+        let timeout = rto
+            .saturating_mul(1 << retries.min(16))
+            .min(self.max_backoff);
+        timeout.mul_f64(1.0 + self.jitter * unit)
+    }
+
+    pub fn is_exhausted(&self, retries: u32) -> bool {
+        retries > self.max_retries
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn test_backoff() {
+        let rto = Duration::from_secs(1);
+        let policy = RetryPolicy {
+            max_backoff: Duration::from_secs(10),
+            max_retries: 3,
+            jitter: 0.5,
+        };
+
+        assert_eq!(policy.backoff_with(rto, 0, 0.0), Duration::from_secs(1));
+        assert_eq!(policy.backoff_with(rto, 2, 0.0), Duration::from_secs(4));
+        // capped
+        assert_eq!(policy.backoff_with(rto, 5, 0.0), Duration::from_secs(10));
+        assert_eq!(policy.backoff_with(rto, 40, 0.0), Duration::from_secs(10));
+        // jitter
+        assert_eq!(policy.backoff_with(rto, 1, 1.0), Duration::from_secs(3));
+        assert_eq!(policy.backoff_with(rto, 1, -1.0), Duration::from_secs(1));
+
+        let timeout = policy.backoff(rto, 0);
+        assert!(timeout >= Duration::from_millis(500) && timeout <= Duration::from_millis(1500));
+
+        assert!(!policy.is_exhausted(3));
+        assert!(policy.is_exhausted(4));
+    }
+}
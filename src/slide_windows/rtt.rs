@@ -0,0 +1,104 @@
+//! RTT 估计与重传超时计算（RFC 6298）
+
+use std::time::Duration;
+
+use super::TIMEOUT_MS;
+
+/// 重传超时的下限
+pub const MIN_RTO: Duration = Duration::from_millis(200);
+/// 重传超时的上限
+pub const MAX_RTO: Duration = Duration::from_secs(60);
+/// 时钟粒度
+const CLOCK_GRANULARITY: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, Copy)]
+pub struct RttEstimator {
+    /// 平滑后的 RTT，尚未采样时为 `None`
+    srtt: Option<Duration>,
+    /// RTT 的平均偏差
+    rttvar: Duration,
+    rto: Duration,
+}
+
+impl Default for RttEstimator {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(TIMEOUT_MS))
+    }
+}
+
+impl RttEstimator {
+    /// `initial` 为尚未测得 RTT 时使用的超时时间
+    pub fn new(initial: Duration) -> Self {
+        Self {
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: initial.clamp(MIN_RTO, MAX_RTO),
+        }
+    }
+
+    /// 加入一个 RTT 样本，重传过的 packet 不应采样（Karn 算法）
+    pub fn on_sample(&mut self, rtt: Duration) {
+        let srtt = match self.srtt {
+            None => {
+                self.rttvar = rtt / 2;
+                rtt
+            }
+            Some(srtt) => {
+                // RTTVAR = 3/4 * RTTVAR + 1/4 * |SRTT - R|
+                self.rttvar = (self.rttvar * 3 + srtt.abs_diff(rtt)) / 4;
+                // SRTT = 7/8 * SRTT + 1/8 * R
+                (srtt * 7 + rtt) / 8
+            }
+        };
+        self.srtt = Some(srtt);
+        self.rto = (srtt + (self.rttvar * 4).max(CLOCK_GRANULARITY)).clamp(MIN_RTO, MAX_RTO);
+    }
+
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    pub fn rttvar(&self) -> Duration {
+        self.rttvar
+    }
+
+    /// 当前的重传超时时间
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{RttEstimator, MAX_RTO, MIN_RTO};
+
+    #[test]
+    fn test_rtt_estimate() {
+        let mut rtt = RttEstimator::new(Duration::from_secs(1));
+        assert_eq!(rtt.srtt(), None);
+        assert_eq!(rtt.rto(), Duration::from_secs(1));
+
+        // first sample: SRTT = R, RTTVAR = R / 2
+        rtt.on_sample(Duration::from_millis(400));
+        assert_eq!(rtt.srtt(), Some(Duration::from_millis(400)));
+        assert_eq!(rtt.rttvar(), Duration::from_millis(200));
+        assert_eq!(rtt.rto(), Duration::from_millis(1200));
+
+        rtt.on_sample(Duration::from_millis(800));
+        assert_eq!(rtt.rttvar(), Duration::from_millis(250));
+        assert_eq!(rtt.srtt(), Some(Duration::from_millis(450)));
+        assert_eq!(rtt.rto(), Duration::from_millis(1450));
+
+        // loopback rtt is clamped by min rto
+        let mut rtt = RttEstimator::default();
+        for _ in 0..32 {
+            rtt.on_sample(Duration::from_micros(50));
+        }
+        assert_eq!(rtt.rto(), MIN_RTO);
+
+        rtt.on_sample(Duration::from_secs(100));
+        assert_eq!(rtt.rto(), MAX_RTO);
+    }
+}
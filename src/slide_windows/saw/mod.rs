@@ -0,0 +1,342 @@
+//! Stop and wait
+//! 停等协议
+//!
+//! 同一时间只有一个 packet 等待确认，收到确认后才发送下一个
+
+mod receiver;
+use std::{io, net::SocketAddr, sync::Arc};
+mod sender;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SawError {
+    #[error("Io Error {0}")]
+    Io(#[from] io::Error),
+
+    /// 消息的分段数量超过窗口大小
+    #[error("缓冲区已满")]
+    BufferFilled,
+
+    #[error("Packet 解码错误 {0}")]
+    PacketFault(#[from] PacketDecodeError),
+
+    #[error("Packet ID 不匹配")]
+    PacketIdMisMatch,
+
+    #[error("序号宽度不匹配，期望 {expect:?} 实际 {actual:?}")]
+    SeqWidthMismatch { expect: SeqWidth, actual: SeqWidth },
+
+    #[error(transparent)]
+    MessageTooLarge(#[from] MessageTooLarge),
+
+    #[error(transparent)]
+    Handshake(#[from] HandshakeError),
+
+    #[error("连接未建立")]
+    NotConnected,
+
+    #[error("连接已关闭")]
+    Closed,
+
+    #[error(transparent)]
+    PeerUnreachable(#[from] PeerUnreachable),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error("Packet {packet_id} 重传 {retries} 次后仍未确认")]
+    RetriesExhausted { packet_id: u32, retries: u32 },
+
+    #[error("连接被对端重置，{dropped} 个 packet 未确认")]
+    ConnectionReset { dropped: u32 },
+}
+
+pub use receiver::StopAndWaitReceiver;
+pub use sender::StopAndWaitSender;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    fake_udp::UdpSocket,
+    packet::{
+        handshake::{ArqMode, Handshake, HandshakeStage},
+        keepalive::KeepAlive,
+        seq::SeqWidth,
+        PacketDecodeError, PacketRef,
+    },
+};
+
+use super::{
+    config::{ConfigError, TransportConfig},
+    connect::HandshakeError,
+    keepalive::PeerUnreachable,
+    output::Output,
+    rtt::RttEstimator,
+    transport::Transport,
+    Message, MessageTooLarge, MAX_BUFF_SIZE,
+};
+
+pub use super::RecvMsg;
+
+#[derive(Debug)]
+pub enum SenderMsg {
+    /// 应用层的一条消息
+    Msg(Message),
+    Ack(u32),
+    Handshake(Handshake),
+    /// 发送完全部数据后关闭连接，完成时通知
+    Close(oneshot::Sender<()>),
+    /// 对端回复的 Pong
+    Pong,
+    /// 定期的存活检查
+    KeepAlive,
+    /// 查询当前的 RTT 估计
+    Rtt(oneshot::Sender<RttEstimator>),
+    Resend,
+}
+
+pub fn start_send_peer(
+    socket: Arc<UdpSocket>,
+    target: SocketAddr,
+    timeout_send: mpsc::Sender<()>,
+    config: TransportConfig,
+    fatal: oneshot::Sender<SawError>,
+) -> Result<mpsc::Sender<SenderMsg>, ConfigError> {
+    config.validate(ArqMode::StopAndWait)?;
+    let (rx, mut tx) = mpsc::channel(128);
+    let mut sender = StopAndWaitSender::new(target, &config);
+
+    // 定期触发存活检查，发送端结束后通道关闭，随之退出
+    let ticker = rx.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(config.keepalive.tick_period()).await;
+            if ticker.send(SenderMsg::KeepAlive).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let task = async move {
+        let mut write_buf = Vec::with_capacity(MAX_BUFF_SIZE);
+        if let Err(err) = sender
+            .connect(&mut write_buf, &socket, timeout_send.clone())
+            .await
+        {
+            eprintln!("Error 发生 {err}");
+        }
+        while let Some(msg) = tx.recv().await {
+            if matches!(
+                msg,
+                SenderMsg::Ack(_) | SenderMsg::Handshake(_) | SenderMsg::Pong
+            ) {
+                sender.on_peer_packet();
+            }
+            let result = async {
+                match msg {
+                    SenderMsg::Ack(ack) => {
+                        sender
+                            .recv_ack(ack, &mut write_buf, &socket, timeout_send.clone())
+                            .await
+                    }
+                    SenderMsg::Handshake(handshake) => {
+                        sender
+                            .recv_handshake(
+                                handshake,
+                                &mut write_buf,
+                                &socket,
+                                timeout_send.clone(),
+                            )
+                            .await
+                    }
+                    SenderMsg::Close(notify) => {
+                        sender.close(notify);
+                        Ok(())
+                    }
+                    SenderMsg::Pong => Ok(()),
+                    SenderMsg::Rtt(reply) => {
+                        reply.send(*sender.rtt()).ok();
+                        Ok(())
+                    }
+                    SenderMsg::KeepAlive => sender.keepalive(&mut write_buf, &socket).await,
+                    SenderMsg::Resend => {
+                        sender
+                            .resend(&mut write_buf, &socket, timeout_send.clone())
+                            .await
+                    }
+                    SenderMsg::Msg(msg) => {
+                        sender
+                            .send(&mut write_buf, msg, &socket, timeout_send.clone())
+                            .await
+                    }
+                }
+            };
+
+            match result.await {
+                Ok(_) => (),
+                // 对端不可达、重传次数用尽或未确认的数据被对端丢弃，发送端停止并通知应用层
+                Err(
+                    err @ (SawError::PeerUnreachable(_)
+                    | SawError::RetriesExhausted { .. }
+                    | SawError::ConnectionReset { .. }),
+                ) => {
+                    eprintln!("Error 发生 {err}");
+                    fatal.send(err).ok();
+                    break;
+                }
+                Err(err) => {
+                    eprintln!("Error 发生 {err}");
+                }
+            }
+
+            if let Err(err) = sender
+                .poll_pending(&mut write_buf, &socket, timeout_send.clone())
+                .await
+            {
+                eprintln!("Error 发生 {err}");
+            }
+            if let Err(err) = sender
+                .poll_close(&mut write_buf, &socket, timeout_send.clone())
+                .await
+            {
+                eprintln!("Error 发生 {err}");
+            }
+            if sender.is_closed() {
+                println!("Sender to {target} closed");
+                break;
+            }
+        }
+    };
+
+    tokio::spawn(task);
+    Ok(rx)
+}
+
+pub fn start_receive_peer(
+    socket: Arc<UdpSocket>,
+    origin: SocketAddr,
+    output: mpsc::Sender<Vec<u8>>,
+    config: TransportConfig,
+) -> Result<mpsc::Sender<RecvMsg>, ConfigError> {
+    config.validate(ArqMode::StopAndWait)?;
+    let (rx, mut tx) = mpsc::channel(128);
+    let mut receiver = StopAndWaitReceiver::new(origin, &config);
+
+    let task = async move {
+        let mut write_buf = Vec::with_capacity(MAX_BUFF_SIZE);
+        // 输出队列已满时不再接收新的 packet，最多暂存一条消息
+        let mut output = Output::new(output, 1);
+        loop {
+            // 应用层读取较慢时不阻塞，继续回复 Ping 与重复的确认
+            let packet = tokio::select! {
+                recv = tx.recv() => match recv {
+                    Some(RecvMsg(packet)) => packet,
+                    None => break,
+                },
+                _ = output.drain(), if output.is_blocked() => continue,
+            };
+            let result = async {
+                let packet = packet.map_err(|err| receiver.record_decode_error(err))?;
+                if let Some(handshake) = packet.as_handshake() {
+                    receiver
+                        .recv_handshake(handshake, &mut write_buf, &socket)
+                        .await?;
+                } else if let Some(KeepAlive::Ping) = packet.as_keepalive() {
+                    receiver
+                        .send_pong(packet.get_id(), &mut write_buf, &socket)
+                        .await?;
+                } else if packet.is_leave() {
+                    return receiver
+                        .recv_leave(packet.get_id(), &mut write_buf, &socket)
+                        .await;
+                } else if packet.is_data() && output.is_blocked() {
+                    // 停等协议没有通告窗口，输出队列已满时不接收新的 packet，
+                    // 只重复上一次的确认，发送端超时后重传
+                    receiver.send_ack(&mut write_buf, &socket).await?;
+                } else if packet.is_data() {
+                    let v = receiver.receive(&mut write_buf, packet, &socket).await?;
+                    if let Some(v) = v {
+                        output.push(v);
+                    }
+                }
+                Result::<_, SawError>::Ok(false)
+            };
+
+            match result.await {
+                Ok(true) => {
+                    println!("Receiver from {origin} closed");
+                    break;
+                }
+                Ok(false) => (),
+                Err(err) => {
+                    if let SawError::PacketFault(_) = err {
+                        // 损坏的 packet 无法得知序号，重复上一次的确认
+                        receiver.send_ack(&mut write_buf, &socket).await.ok();
+                    }
+                    eprintln!("Error 发生 {err}")
+                }
+            }
+        }
+        output.finish().await;
+    };
+
+    tokio::task::spawn(task);
+
+    Ok(rx)
+}
+
+/// 停等协议，实现 [`Transport`]
+pub struct StopAndWait;
+
+impl Transport for StopAndWait {
+    type SenderMsg = SenderMsg;
+    type Timeout = ();
+    type Error = SawError;
+
+    const MODE: ArqMode = ArqMode::StopAndWait;
+
+    fn start_send_peer(
+        socket: Arc<UdpSocket>,
+        target: SocketAddr,
+        timeout_send: mpsc::Sender<()>,
+        config: TransportConfig,
+        fatal: oneshot::Sender<SawError>,
+    ) -> Result<mpsc::Sender<SenderMsg>, ConfigError> {
+        start_send_peer(socket, target, timeout_send, config, fatal)
+    }
+
+    fn start_receive_peer(
+        socket: Arc<UdpSocket>,
+        origin: SocketAddr,
+        output: mpsc::Sender<Vec<u8>>,
+        config: TransportConfig,
+    ) -> Result<mpsc::Sender<RecvMsg>, ConfigError> {
+        start_receive_peer(socket, origin, output, config)
+    }
+
+    fn message(msg: Message) -> SenderMsg {
+        SenderMsg::Msg(msg)
+    }
+
+    fn close(notify: oneshot::Sender<()>) -> SenderMsg {
+        SenderMsg::Close(notify)
+    }
+
+    fn timeout(_: ()) -> SenderMsg {
+        SenderMsg::Resend
+    }
+
+    fn feedback(packet: &PacketRef<'_>) -> Option<SenderMsg> {
+        // SYN-ACK 与 Reset 由本端的发送端处理
+        if let Some(handshake) = packet
+            .as_handshake()
+            .filter(|h| matches!(h.stage, HandshakeStage::SynAck | HandshakeStage::Reset))
+        {
+            Some(SenderMsg::Handshake(handshake))
+        } else if let Some(KeepAlive::Pong) = packet.as_keepalive() {
+            Some(SenderMsg::Pong)
+        } else if packet.is_ack() {
+            Some(SenderMsg::Ack(packet.get_ack_num()))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::{io, net::SocketAddr};
+
+use crate::{
+    fake_udp::UdpSocket,
+    packet::{
+        ack::Ack,
+        handshake::{ArqMode, Handshake},
+        seq::SeqWidth,
+        Packet, PacketDecodeError,
+    },
+    slide_windows::{config::TransportConfig, connect::Acceptor, DecodeErrorStats, Reassembler},
+    verify::ChecksumKind,
+};
+
+use super::SawError;
+
+pub struct StopAndWaitReceiver {
+    origin: SocketAddr,
+    last_ack: Ack,
+    /// 期望收到的序号
+    pkg_id: u32,
+    seq_width: SeqWidth,
+    checksum: ChecksumKind,
+    reassembler: Reassembler,
+    acceptor: Acceptor,
+}
+
+impl StopAndWaitReceiver {
+    /// `config` 需要先通过 [`TransportConfig::validate`] 校验
+    pub fn new(origin: SocketAddr, config: &TransportConfig) -> Self {
+        let TransportConfig {
+            seq_width,
+            checksum,
+            ..
+        } = *config;
+        Self {
+            origin,
+            last_ack: Ack::new_ack(seq_width.mask())
+                .with_seq_width(seq_width)
+                .with_checksum(checksum),
+            pkg_id: 0,
+            seq_width,
+            checksum,
+            reassembler: Reassembler::new(config.max_message),
+            acceptor: Acceptor::new(
+                origin,
+                ArqMode::StopAndWait,
+                config.window,
+                seq_width,
+                checksum,
+            ),
+        }
+    }
+
+    /// 处理发送端的 SYN、ACK 与 Abort，新的连接会重置接收状态
+    pub async fn recv_handshake(
+        &mut self,
+        handshake: Handshake,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> Result<(), SawError> {
+        let reset = self
+            .acceptor
+            .recv_handshake::<SawError>(&handshake, buf, socket);
+        if let Some(isn) = reset.await? {
+            self.pkg_id = isn;
+            self.last_ack = Ack::new_ack(self.seq_width.sub(isn, 1))
+                .with_seq_width(self.seq_width)
+                .with_checksum(self.checksum);
+            self.reassembler = Reassembler::new(self.reassembler.max_size());
+        }
+
+        Ok(())
+    }
+
+    /// 记录一次解码失败，返回原错误便于继续传递
+    pub fn record_decode_error(&mut self, err: PacketDecodeError) -> PacketDecodeError {
+        self.acceptor.record_decode_error(err)
+    }
+
+    pub fn decode_errors(&self) -> &DecodeErrorStats {
+        self.acceptor.decode_errors()
+    }
+
+    /// 接收一个 packet，分段消息在收到 End 分段后才返回完整消息
+    pub async fn receive(
+        &mut self,
+        buf: &mut Vec<u8>,
+        packet: Packet,
+        socket: &UdpSocket,
+    ) -> Result<Option<Vec<u8>>, SawError> {
+        if !self.acceptor.is_connected() {
+            // 没有连接，要求发送端重新握手
+            self.acceptor
+                .send_reset(packet.get_id(), buf, socket)
+                .await?;
+            Err(SawError::NotConnected)?
+        }
+        let resp = if packet.seq_width() != self.seq_width {
+            Err(SawError::SeqWidthMismatch {
+                expect: self.seq_width,
+                actual: packet.seq_width(),
+            })
+        } else if packet.get_id() == self.pkg_id {
+            self.last_ack = Ack::new_ack(self.pkg_id)
+                .with_seq_width(self.seq_width)
+                .with_checksum(self.checksum);
+            self.pkg_id = self.seq_width.add(self.pkg_id, 1);
+            let split = packet.packet_split();
+            self.reassembler
+                .push(split, packet.get_body())
+                .map_err(SawError::from)
+        } else {
+            // 重复的 packet，说明确认丢失
+            Err(SawError::PacketIdMisMatch)
+        };
+
+        self.send_ack(buf, socket).await?;
+
+        resp
+    }
+
+    /// 处理 Leave，发送端的数据全部按序收到后确认并返回 true，接收端随后结束
+    pub async fn recv_leave(
+        &mut self,
+        leave_id: u32,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> Result<bool, SawError> {
+        let expect = self.pkg_id;
+        Ok(self
+            .acceptor
+            .recv_leave(leave_id, expect, buf, socket)
+            .await?)
+    }
+
+    /// 回复发送端的 Ping
+    pub async fn send_pong(
+        &self,
+        ping_id: u32,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> io::Result<()> {
+        self.acceptor.send_pong(ping_id, buf, socket).await
+    }
+
+    /// 重复上一次的确认
+    pub async fn send_ack(&self, buf: &mut Vec<u8>, socket: &UdpSocket) -> io::Result<()> {
+        buf.clear();
+        let size = self.last_ack.write(buf)?;
+        let send_body = &buf[0..size];
+
+        socket.send_to(send_body, self.origin).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,462 @@
+use std::{collections::VecDeque, net::SocketAddr, time::Instant};
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    fake_udp::UdpSocket,
+    packet::{
+        flags::{PackSplit, PacketType},
+        handshake::{ArqMode, Handshake, HandshakeStage},
+        seq::SeqWidth,
+        Packet,
+    },
+    slide_windows::{
+        close::Closing,
+        config::TransportConfig,
+        connect::{Connector, HandshakeError},
+        keepalive::Liveness,
+        retry::RetryPolicy,
+        rtt::RttEstimator,
+        segment_count, split_segments, Message, StatePacket, Timer,
+    },
+    verify::ChecksumKind,
+};
+
+use super::SawError;
+
+/// 等待发送的分段
+struct Segment {
+    split: PackSplit,
+    body: Vec<u8>,
+    /// 消息的最后一个分段携带送达通知
+    delivery: Option<oneshot::Sender<()>>,
+}
+
+pub struct StopAndWaitSender {
+    target: SocketAddr,
+    /// 等待确认的 packet
+    waiting: Option<StatePacket>,
+    /// 等待发送的分段
+    queue: VecDeque<Segment>,
+    /// 下一个 packet 的序号
+    next_id: u32,
+    seq_width: SeqWidth,
+    timer: Option<Timer>,
+    /// 单个 packet body 的最大长度
+    max_segment: usize,
+    checksum: ChecksumKind,
+    connector: Connector,
+    /// 最多等待发送的分段数量
+    capacity: u32,
+    /// 等待分段队列空余的消息
+    pending: VecDeque<Message>,
+    closing: Option<Closing>,
+    liveness: Liveness,
+    retry: RetryPolicy,
+    rtt: RttEstimator,
+}
+
+impl StopAndWaitSender {
+    /// `config` 需要先通过 [`TransportConfig::validate`] 校验
+    pub fn new(target: SocketAddr, config: &TransportConfig) -> Self {
+        assert!(config.max_segment > 0, "max segment size must > 0");
+        Self {
+            target,
+            waiting: None,
+            queue: VecDeque::new(),
+            next_id: 0,
+            seq_width: config.seq_width,
+            timer: None,
+            max_segment: config.max_segment,
+            checksum: config.checksum,
+            // 停等协议不需要协商窗口
+            connector: Connector::new(ArqMode::StopAndWait, 1, config.seq_width, config.checksum),
+            capacity: config.window,
+            pending: VecDeque::new(),
+            closing: None,
+            liveness: Liveness::new(config.keepalive),
+            retry: config.retry,
+            rtt: RttEstimator::new(config.initial_rto),
+        }
+    }
+
+    /// 当前的 RTT 估计
+    pub fn rtt(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
+    /// 收到对端的任意 packet
+    pub fn on_peer_packet(&mut self) {
+        self.liveness.on_recv();
+    }
+
+    /// 定期检查对端状态，空闲时发送 Ping，对端不可达时停止全部定时器
+    pub async fn keepalive(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> Result<(), SawError> {
+        let ping = match self
+            .liveness
+            .tick(Instant::now(), self.target, self.seq_width)
+        {
+            Ok(ping) => ping,
+            Err(err) => {
+                self.abort();
+                Err(err)?
+            }
+        };
+
+        if let Some(ping) = ping {
+            let ping = ping.with_checksum(self.checksum);
+            buf.clear();
+            let size = ping.write(buf)?;
+            socket.send_to(&buf[0..size], self.target).await?;
+            println!("Send Ping [{}]", ping.get_id());
+        }
+
+        Ok(())
+    }
+
+    /// 放弃连接，停止全部定时器并丢弃等待确认、等待发送与握手期间缓存的消息
+    ///
+    /// 这些消息的送达通知随之返回 `Undelivered`；关闭流程无法完成，等待方同样收到通知失败
+    fn abort(&mut self) {
+        if let Some(t) = self.timer.take() {
+            t.stop();
+        }
+        self.connector.stop_timer();
+        self.connector.clear_pending();
+        self.waiting = None;
+        self.queue.clear();
+        self.pending.clear();
+        self.closing = None;
+    }
+
+    /// Reset 携带接收端拒绝的 packet 序号，只接受正在等待确认或刚确认的序号，
+    /// 避免延迟到达或伪造的 Reset 中断当前连接
+    fn accepts_reset(&self, seq: u32) -> bool {
+        self.seq_width.sub(self.next_id, seq) <= 1
+    }
+
+    fn is_idle(&self) -> bool {
+        self.waiting.is_none() && self.queue.is_empty() && self.pending.is_empty()
+    }
+
+    /// 开始关闭连接，全部分段确认后发送 Leave，关闭完成时通知 `notify`
+    pub fn close(&mut self, notify: oneshot::Sender<()>) {
+        let mut closing = Closing::new(notify);
+        if !self.connector.is_established() && !self.connector.has_pending() {
+            // 连接尚未建立，没有需要等待的数据
+            closing.finish();
+        }
+        self.closing = Some(closing);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closing.as_ref().is_some_and(Closing::is_closed)
+    }
+
+    /// 关闭中、连接已建立且全部分段确认后发送 Leave
+    pub async fn poll_close(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), SawError> {
+        // 连接建立前缓存的消息还没有发送
+        if !self.connector.is_established() || !self.is_idle() {
+            return Ok(());
+        }
+        let closing = match self.closing.as_mut() {
+            Some(closing) if closing.need_leave() => closing,
+            _ => return Ok(()),
+        };
+        let leave = match closing.leave(self.next_id, self.seq_width, self.checksum) {
+            Some(leave) => leave,
+            None => return Ok(()),
+        };
+
+        buf.clear();
+        let size = leave.write(buf)?;
+        socket.send_to(&buf[0..size], self.target).await?;
+        println!("Send Leave [{}]", leave.get_id());
+
+        let (timer, timeout) = Timer::start(self.rtt.rto());
+        closing.set_timer(timer);
+        timeout.need_resend_do(async move {
+            eprintln!("waiting Leave ACK timeout , resend");
+            timeout_send.send(()).await.ok();
+        });
+
+        Ok(())
+    }
+
+    /// 发送 SYN 发起连接，超时后通过 `timeout_send` 触发重传
+    pub async fn connect(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), SawError> {
+        let syn = self.connector.syn();
+        buf.clear();
+        let size = syn.write(buf)?;
+        socket.send_to(&buf[0..size], self.target).await?;
+        println!("Send SYN [{}]", self.connector.isn());
+
+        let (timer, timeout) = Timer::start(self.rtt.rto());
+        self.connector.set_timer(timer);
+        timeout.need_resend_do(async move {
+            eprintln!("waiting SYN-ACK timeout , resend");
+            timeout_send.send(()).await.ok();
+        });
+
+        Ok(())
+    }
+
+    /// 处理接收端回复的 SYN-ACK 与 Reset
+    pub async fn recv_handshake(
+        &mut self,
+        handshake: Handshake,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), SawError> {
+        match handshake.stage {
+            HandshakeStage::SynAck => {
+                let established = match self.connector.on_syn_ack(&handshake) {
+                    // 对端仍保留着旧连接，要求其结束旧连接，之后重传的 SYN 即可建立新连接
+                    Err(HandshakeError::Challenged { current }) => {
+                        eprintln!("Peer still holds connection isn [{current}], abort it");
+                        let abort = self.connector.abort(current);
+                        buf.clear();
+                        let size = abort.write(buf)?;
+                        socket.send_to(&buf[0..size], self.target).await?;
+                        return Ok(());
+                    }
+                    result => result?,
+                };
+                let ack = self.connector.ack();
+                buf.clear();
+                let size = ack.write(buf)?;
+                socket.send_to(&buf[0..size], self.target).await?;
+
+                if let Some(established) = established {
+                    println!("Connection established isn [{}]", established.isn);
+                    self.next_id = established.isn;
+                    // 连接建立前缓存的消息，即使已经开始关闭也需要发送
+                    self.pending.extend(established.pending);
+                    self.poll_pending(buf, socket, timeout_send).await?;
+                }
+            }
+            // 接收端丢失了连接，没有未确认的分段时重新握手，否则这部分数据已经无法送达
+            HandshakeStage::Reset
+                if self.connector.is_established() && self.accepts_reset(handshake.isn) =>
+            {
+                let dropped = self.queue.len() as u32 + u32::from(self.waiting.is_some());
+                if dropped > 0 {
+                    self.abort();
+                    Err(SawError::ConnectionReset { dropped })?
+                }
+                if let Some(t) = self.timer.take() {
+                    t.stop();
+                }
+                eprintln!("Connection reset by peer, reconnect");
+                self.connector.restart();
+                self.connect(buf, socket, timeout_send).await?;
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// 发送一条消息，超过最大分段长度的消息会被切分为多个 packet
+    ///
+    /// 等待发送的分段超过窗口大小时，消息进入发送队列等待
+    pub async fn send(
+        &mut self,
+        buf: &mut Vec<u8>,
+        msg: Message,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), SawError> {
+        if self.closing.is_some() {
+            Err(SawError::Closed)?
+        }
+        if !self.connector.is_established() {
+            self.connector.queue(msg);
+            return Ok(());
+        }
+        self.pending.push_back(msg);
+        self.poll_pending(buf, socket, timeout_send).await
+    }
+
+    /// 按顺序将等待中的消息切分加入分段队列
+    pub async fn poll_pending(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), SawError> {
+        if !self.connector.is_established() {
+            return Ok(());
+        }
+        while let Some(msg) = self.pending.front() {
+            let segments = segment_count(msg.body.len(), self.max_segment);
+            if segments > self.capacity as usize {
+                // 分段数量超过整个队列，永远无法发送
+                self.pending.pop_front();
+                Err(SawError::BufferFilled)?
+            }
+            if self.queue.len() + segments > self.capacity as usize {
+                break;
+            }
+            let msg = self.pending.pop_front().expect("pending message exists");
+            self.send_message(buf, msg, socket, timeout_send.clone())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn send_message(
+        &mut self,
+        buf: &mut Vec<u8>,
+        msg: Message,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), SawError> {
+        let segments = split_segments(msg.body, self.max_segment);
+        if self.queue.len() + segments.len() > self.capacity as usize {
+            Err(SawError::BufferFilled)?
+        }
+        let mut delivery = msg.delivery;
+        self.queue
+            .extend(segments.into_iter().map(|(split, body)| Segment {
+                split,
+                body,
+                delivery: match split {
+                    PackSplit::End => delivery.take(),
+                    PackSplit::Follow => None,
+                },
+            }));
+
+        self.send_next(buf, socket, timeout_send).await
+    }
+
+    /// 上一个 packet 已确认时发送下一个分段
+    async fn send_next(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), SawError> {
+        if self.waiting.is_some() {
+            return Ok(());
+        }
+        let segment = match self.queue.pop_front() {
+            Some(segment) => segment,
+            None => return Ok(()),
+        };
+
+        let packet = Packet::new(self.next_id, segment.body, PacketType::Data, segment.split)
+            .with_seq_width(self.seq_width)
+            .with_checksum(self.checksum);
+        buf.clear();
+        let size = packet.write(buf)?;
+        self.waiting = Some(StatePacket::new_waiting(packet).with_delivery(segment.delivery));
+
+        let len = socket.send_to(&buf[0..size], self.target).await?;
+        println!("Send Packet {} size {len}", self.next_id);
+
+        self.start_timer(self.retry.backoff(self.rtt.rto(), 0), timeout_send);
+
+        Ok(())
+    }
+
+    fn start_timer(&mut self, timeout: std::time::Duration, timeout_send: mpsc::Sender<()>) {
+        let (timer, timeout) = Timer::start(timeout);
+        if let Some(t) = self.timer.replace(timer) {
+            t.stop();
+        }
+        timeout.need_resend_do(async move {
+            eprintln!("waiting timeout , resend");
+            timeout_send.send(()).await.ok();
+        });
+    }
+
+    /// 确认当前等待的 packet 后发送下一个分段，其余确认均为重复确认
+    pub async fn recv_ack(
+        &mut self,
+        ack_num: u32,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), SawError> {
+        if let Some(closing) = self.closing.as_mut() {
+            closing.on_ack(ack_num);
+        }
+        match self.waiting.as_mut() {
+            Some(waiting) if waiting.pkg.get_id() == ack_num => {
+                println!("ACK PASS");
+                if let Some(rtt) = waiting.rtt_sample() {
+                    self.rtt.on_sample(rtt);
+                }
+                waiting.recv_ack();
+                self.waiting = None;
+                if let Some(t) = self.timer.take() {
+                    t.stop();
+                }
+                self.next_id = self.seq_width.add(self.next_id, 1);
+                self.send_next(buf, socket, timeout_send).await
+            }
+            _ => {
+                println!("ACK num {ack_num} duplicate, waiting for time out re send");
+                Ok(())
+            }
+        }
+    }
+
+    /// 超时重传等待确认的 packet
+    pub async fn resend(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<()>,
+    ) -> Result<(), SawError> {
+        // 连接未建立时超时重传 SYN
+        if !self.connector.is_established() {
+            return self.connect(buf, socket, timeout_send).await;
+        }
+        // Leave 超时，由 poll_close 重传
+        if let Some(closing) = self.closing.as_mut() {
+            if closing.leave_seq().is_some() {
+                closing.on_timeout();
+                return Ok(());
+            }
+        }
+        let waiting = match self.waiting.as_mut() {
+            Some(waiting) => waiting,
+            None => return Ok(()),
+        };
+        let retries = waiting.retry();
+        let packet_id = waiting.pkg.get_id();
+        if self.retry.is_exhausted(retries) {
+            self.abort();
+            return Err(SawError::RetriesExhausted {
+                packet_id,
+                retries: retries - 1,
+            });
+        }
+
+        buf.clear();
+        let size = waiting.pkg.write(buf)?;
+        let len = socket.send_to(&buf[0..size], self.target).await?;
+        println!("Resend Packet {packet_id} size: [{len}]");
+
+        self.start_timer(self.retry.backoff(self.rtt.rto(), retries), timeout_send);
+
+        Ok(())
+    }
+}
@@ -4,16 +4,36 @@
 //!
 mod receiver;
 mod sender;
-const MAX_WINDOWS_SIZE: u8 = 128;
 use std::{io, net::SocketAddr, sync::Arc};
 
 pub use receiver::SelectResendReceiver;
 pub use sender::SelectResendSender;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 
-use crate::{cycle_buffer::CbError, fake_udp::UdpSocket, packet::Packet};
+use crate::{
+    cycle_buffer::CbError,
+    fake_udp::UdpSocket,
+    packet::{
+        handshake::{ArqMode, Handshake, HandshakeStage},
+        keepalive::KeepAlive,
+        sack::Sack,
+        seq::SeqWidth,
+        PacketDecodeError, PacketRef,
+    },
+};
 
-use super::MAX_BUFF_SIZE;
+use super::{
+    config::{ConfigError, TransportConfig},
+    congestion::Loss,
+    connect::HandshakeError,
+    keepalive::PeerUnreachable,
+    output::Output,
+    rtt::RttEstimator,
+    transport::Transport,
+    Message, MessageTooLarge, MAX_BUFF_SIZE,
+};
+
+pub use super::RecvMsg;
 
 #[derive(Debug, thiserror::Error)]
 pub enum SrError {
@@ -21,27 +41,91 @@ pub enum SrError {
     Io(#[from] io::Error),
     #[error(transparent)]
     CycleBuffer(#[from] CbError),
+    #[error(transparent)]
+    MessageTooLarge(#[from] MessageTooLarge),
+    #[error("Packet 解码错误 {0}")]
+    PacketFault(#[from] PacketDecodeError),
+    #[error("序号宽度不匹配，期望 {expect:?} 实际 {actual:?}")]
+    SeqWidthMismatch { expect: SeqWidth, actual: SeqWidth },
+    #[error(transparent)]
+    Handshake(#[from] HandshakeError),
+    #[error("连接未建立")]
+    NotConnected,
+    #[error("连接已关闭")]
+    Closed,
+    #[error(transparent)]
+    PeerUnreachable(#[from] PeerUnreachable),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("Packet {packet_id} 重传 {retries} 次后仍未确认")]
+    RetriesExhausted { packet_id: u32, retries: u32 },
+    #[error("连接被对端重置，{dropped} 个 packet 未确认")]
+    ConnectionReset { dropped: u32 },
 }
 
 #[derive(Debug)]
 pub enum SenderMsg {
-    Msg(Vec<u8>),
-    Ack(u8),
-    Resend(u8),
+    /// 应用层的一条消息
+    Msg(Message),
+    /// 累计确认号与对端通告的接收窗口
+    Ack(u32, Option<u32>),
+    Sack(Sack),
+    Nak(u32),
+    Handshake(Handshake),
+    /// 发送完窗口内的数据后关闭连接，完成时通知
+    Close(oneshot::Sender<()>),
+    /// 对端回复的 Pong
+    Pong,
+    /// 定期的存活检查
+    KeepAlive,
+    /// 查询当前的 RTT 估计
+    Rtt(oneshot::Sender<RttEstimator>),
+    Resend(u32),
 }
 
 pub fn start_send_peer(
     socket: Arc<UdpSocket>,
     target: SocketAddr,
-    timeout_send: mpsc::Sender<u8>,
-) -> mpsc::Sender<SenderMsg> {
+    timeout_send: mpsc::Sender<u32>,
+    config: TransportConfig,
+    fatal: oneshot::Sender<SrError>,
+) -> Result<mpsc::Sender<SenderMsg>, ConfigError> {
+    config.validate(ArqMode::SelectResend)?;
     let (rx, mut tx) = mpsc::channel(128);
-    let mut sender = SelectResendSender::new(target);
+    let mut sender = SelectResendSender::new(target, &config);
+
+    // 定期触发存活检查，发送端结束后通道关闭，随之退出
+    let ticker = rx.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(config.keepalive.tick_period()).await;
+            if ticker.send(SenderMsg::KeepAlive).await.is_err() {
+                break;
+            }
+        }
+    });
 
     let task = async move {
         let mut write_buf = Vec::with_capacity(MAX_BUFF_SIZE);
+        if let Err(err) = sender
+            .connect(&mut write_buf, &socket, timeout_send.clone())
+            .await
+        {
+            eprintln!("Send Error {}", err)
+        }
 
         while let Some(msg) = tx.recv().await {
+            if matches!(
+                msg,
+                SenderMsg::Ack(..)
+                    | SenderMsg::Sack(_)
+                    | SenderMsg::Nak(_)
+                    | SenderMsg::Handshake(_)
+                    | SenderMsg::Pong
+            ) {
+                sender.on_peer_packet();
+            }
             let result = async {
                 match msg {
                     SenderMsg::Msg(msg) => {
@@ -49,13 +133,54 @@ pub fn start_send_peer(
                             .send(&mut write_buf, msg, &socket, timeout_send.clone())
                             .await
                     }
-                    SenderMsg::Ack(ack) => {
-                        sender.recv_ack(ack).await;
+                    SenderMsg::Ack(ack, window) => {
+                        sender.recv_ack(ack, window).await;
                         Ok(())
                     }
+                    SenderMsg::Sack(sack) => {
+                        sender.recv_sack(&sack).await;
+                        Ok(())
+                    }
+                    SenderMsg::Handshake(handshake) => {
+                        sender
+                            .recv_handshake(
+                                handshake,
+                                &mut write_buf,
+                                &socket,
+                                timeout_send.clone(),
+                            )
+                            .await
+                    }
+                    SenderMsg::Close(notify) => {
+                        sender.close(notify);
+                        Ok(())
+                    }
+                    SenderMsg::Pong => Ok(()),
+                    SenderMsg::Rtt(reply) => {
+                        reply.send(*sender.rtt()).ok();
+                        Ok(())
+                    }
+                    SenderMsg::KeepAlive => sender.keepalive(&mut write_buf, &socket).await,
+                    SenderMsg::Nak(packet_id) => {
+                        sender
+                            .select_resend(
+                                packet_id,
+                                Loss::Fast,
+                                &mut write_buf,
+                                &socket,
+                                timeout_send.clone(),
+                            )
+                            .await
+                    }
                     SenderMsg::Resend(packet_id) => {
                         sender
-                            .select_resend(packet_id, &mut write_buf, &socket, timeout_send.clone())
+                            .select_resend(
+                                packet_id,
+                                Loss::Timeout,
+                                &mut write_buf,
+                                &socket,
+                                timeout_send.clone(),
+                            )
                             .await
                     }
                 }
@@ -63,53 +188,185 @@ pub fn start_send_peer(
 
             match result.await {
                 Ok(_) => {}
+                // 对端不可达、重传次数用尽或未确认的数据被对端丢弃，发送端停止并通知应用层
+                Err(
+                    err @ (SrError::PeerUnreachable(_)
+                    | SrError::RetriesExhausted { .. }
+                    | SrError::ConnectionReset { .. }),
+                ) => {
+                    eprintln!("Send Error {}", err);
+                    fatal.send(err).ok();
+                    break;
+                }
                 Err(err) => {
                     eprintln!("Send Error {}", err)
                 }
             }
+
+            if let Err(err) = sender
+                .poll_pending(&mut write_buf, &socket, timeout_send.clone())
+                .await
+            {
+                eprintln!("Error 发生 {err}");
+            }
+            if let Err(err) = sender
+                .poll_close(&mut write_buf, &socket, timeout_send.clone())
+                .await
+            {
+                eprintln!("Send Error {}", err)
+            }
+            if sender.is_closed() {
+                println!("Sender to {target} closed");
+                break;
+            }
         }
     };
 
     tokio::spawn(task);
 
-    rx
+    Ok(rx)
 }
 
-pub struct RecvMsg(pub io::Result<Option<Packet>>);
-
 pub fn start_receive_peer(
     socket: Arc<UdpSocket>,
     origin: SocketAddr,
     output: mpsc::Sender<Vec<u8>>,
-) -> mpsc::Sender<RecvMsg> {
+    config: TransportConfig,
+) -> Result<mpsc::Sender<RecvMsg>, ConfigError> {
+    config.validate(ArqMode::SelectResend)?;
     let (rx, mut tx) = mpsc::channel(128);
-    let mut receiver = SelectResendReceiver::new(origin);
+    let mut receiver = SelectResendReceiver::new(origin, &config);
 
     let task = async move {
         let mut write_buf = Vec::with_capacity(MAX_BUFF_SIZE);
-        while let Some(RecvMsg(packet)) = tx.recv().await {
+        // 通告零窗口前已经在途的 packet 最多一个窗口
+        let mut output = Output::new(output, config.window as usize);
+        loop {
+            // 应用层读取较慢时不阻塞，继续处理 packet 并通告零窗口
+            let packet = tokio::select! {
+                recv = tx.recv() => match recv {
+                    Some(RecvMsg(packet)) => packet,
+                    None => break,
+                },
+                _ = output.drain(), if output.is_blocked() => {
+                    // 暂存的消息全部交付后通告重新打开的窗口，发送端无需等待窗口探测
+                    if !output.is_blocked() {
+                        receiver
+                            .send_window(&mut write_buf, &socket, output.room())
+                            .await
+                            .ok();
+                    }
+                    continue;
+                }
+            };
             let result = async {
-                if let Ok(Some(packet)) = packet {
-                    if packet.is_data() {
-                        let recv = receiver.receive(&mut write_buf, packet, &socket).await?;
-                        for vec in recv {
-                            output.send(vec).await.ok();
-                        }
+                let packet = packet.map_err(|err| receiver.record_decode_error(err))?;
+                if let Some(handshake) = packet.as_handshake() {
+                    receiver
+                        .recv_handshake(handshake, &mut write_buf, &socket)
+                        .await?;
+                } else if let Some(KeepAlive::Ping) = packet.as_keepalive() {
+                    receiver
+                        .send_pong(packet.get_id(), &mut write_buf, &socket)
+                        .await?;
+                } else if packet.is_leave() {
+                    return receiver
+                        .recv_leave(packet.get_id(), &mut write_buf, &socket)
+                        .await;
+                } else if packet.is_data() && output.is_full() {
+                    // 暂存的消息达到上限时丢弃 packet，继续通告零窗口
+                    receiver.send_window(&mut write_buf, &socket, 0).await?;
+                } else if packet.is_data() {
+                    let recv = receiver
+                        .receive(&mut write_buf, packet, &socket, output.room())
+                        .await?;
+                    for vec in recv {
+                        output.push(vec);
                     }
                 }
-                Result::<_, SrError>::Ok(())
+                Result::<_, SrError>::Ok(false)
             };
 
             match result.await {
-                Ok(_) => (),
+                Ok(true) => {
+                    println!("Receiver from {origin} closed");
+                    break;
+                }
+                Ok(false) => (),
                 Err(err) => {
+                    if let SrError::PacketFault(_) = err {
+                        // 损坏的 packet 无法得知序号，对窗口起点 NAK
+                        receiver.send_nak(&mut write_buf, &socket).await.ok();
+                    }
                     eprintln!("Recv Error {err}")
                 }
             }
         }
+        output.finish().await;
     };
 
     tokio::spawn(task);
 
-    rx
+    Ok(rx)
+}
+
+/// 选择重传，实现 [`Transport`]
+pub struct SelectResend;
+
+impl Transport for SelectResend {
+    type SenderMsg = SenderMsg;
+    type Timeout = u32;
+    type Error = SrError;
+
+    const MODE: ArqMode = ArqMode::SelectResend;
+
+    fn start_send_peer(
+        socket: Arc<UdpSocket>,
+        target: SocketAddr,
+        timeout_send: mpsc::Sender<u32>,
+        config: TransportConfig,
+        fatal: oneshot::Sender<SrError>,
+    ) -> Result<mpsc::Sender<SenderMsg>, ConfigError> {
+        start_send_peer(socket, target, timeout_send, config, fatal)
+    }
+
+    fn start_receive_peer(
+        socket: Arc<UdpSocket>,
+        origin: SocketAddr,
+        output: mpsc::Sender<Vec<u8>>,
+        config: TransportConfig,
+    ) -> Result<mpsc::Sender<RecvMsg>, ConfigError> {
+        start_receive_peer(socket, origin, output, config)
+    }
+
+    fn message(msg: Message) -> SenderMsg {
+        SenderMsg::Msg(msg)
+    }
+
+    fn close(notify: oneshot::Sender<()>) -> SenderMsg {
+        SenderMsg::Close(notify)
+    }
+
+    fn timeout(packet_id: u32) -> SenderMsg {
+        SenderMsg::Resend(packet_id)
+    }
+
+    fn feedback(packet: &PacketRef<'_>) -> Option<SenderMsg> {
+        if let Some(sack) = packet.as_sack() {
+            Some(SenderMsg::Sack(sack))
+        } else if let Some(handshake) = packet
+            .as_handshake()
+            .filter(|h| matches!(h.stage, HandshakeStage::SynAck | HandshakeStage::Reset))
+        {
+            Some(SenderMsg::Handshake(handshake))
+        } else if let Some(KeepAlive::Pong) = packet.as_keepalive() {
+            Some(SenderMsg::Pong)
+        } else if packet.is_nak() {
+            Some(SenderMsg::Nak(packet.get_nak_num()))
+        } else if packet.is_ack() {
+            Some(SenderMsg::Ack(packet.get_ack_num(), packet.get_window()))
+        } else {
+            None
+        }
+    }
 }
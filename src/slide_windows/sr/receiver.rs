@@ -3,32 +3,99 @@ use std::{io, net::SocketAddr};
 use crate::{
     fake_udp::UdpSocket,
     fixed_cycle_buf::FixedCycleBuffer,
-    packet::{ack::Ack, flags::PackSplit, Packet},
+    packet::{
+        flags::PackSplit,
+        handshake::{ArqMode, Handshake},
+        nak::Nak,
+        sack::Sack,
+        Packet, PacketDecodeError,
+    },
+    slide_windows::{config::TransportConfig, connect::Acceptor, DecodeErrorStats, Reassembler},
+    verify::ChecksumKind,
 };
 
-use super::{SrError, MAX_WINDOWS_SIZE};
+use super::SrError;
 
 pub struct SelectResendReceiver {
     origin: SocketAddr,
-    buffer: FixedCycleBuffer<MAX_WINDOWS_SIZE, RecvWrap>,
-    local_buf: Vec<u8>,
+    buffer: FixedCycleBuffer<RecvWrap>,
+    checksum: ChecksumKind,
+    /// 最近一次 NAK 的序号，同一个缺失序号只 NAK 一次
+    last_nak: Option<u32>,
+    reassembler: Reassembler,
+    acceptor: Acceptor,
 }
 
 impl SelectResendReceiver {
-    pub fn new(origin: SocketAddr) -> Self {
+    /// `config` 需要先通过 [`TransportConfig::validate`] 校验
+    pub fn new(origin: SocketAddr, config: &TransportConfig) -> Self {
         Self {
             origin,
-            buffer: FixedCycleBuffer::new(),
-            local_buf: Vec::new(),
+            buffer: FixedCycleBuffer::new(config.window, config.seq_width),
+            checksum: config.checksum,
+            last_nak: None,
+            reassembler: Reassembler::new(config.max_message),
+            acceptor: Acceptor::new(
+                origin,
+                ArqMode::SelectResend,
+                config.window,
+                config.seq_width,
+                config.checksum,
+            ),
         }
     }
 
+    /// 处理发送端的 SYN、ACK 与 Abort，新的连接会重置接收窗口
+    pub async fn recv_handshake(
+        &mut self,
+        handshake: Handshake,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> Result<(), SrError> {
+        let reset = self
+            .acceptor
+            .recv_handshake::<SrError>(&handshake, buf, socket);
+        if let Some(isn) = reset.await? {
+            self.buffer.reset(isn);
+            self.last_nak = None;
+            self.reassembler = Reassembler::new(self.reassembler.max_size());
+        }
+
+        Ok(())
+    }
+
+    /// 记录一次解码失败，返回原错误便于继续传递
+    pub fn record_decode_error(&mut self, err: PacketDecodeError) -> PacketDecodeError {
+        self.acceptor.record_decode_error(err)
+    }
+
+    pub fn decode_errors(&self) -> &DecodeErrorStats {
+        self.acceptor.decode_errors()
+    }
+
+    /// 接收一个 packet，返回窗口滑动后重组完成的消息
+    ///
+    /// SACK 通告的窗口不超过接收窗口的空位与输出队列的剩余空间 `room`
     pub async fn receive(
         &mut self,
         buf: &mut Vec<u8>,
         packet: Packet,
         socket: &UdpSocket,
+        room: usize,
     ) -> Result<Vec<Vec<u8>>, SrError> {
+        if !self.acceptor.is_connected() {
+            // 没有连接，要求发送端重新握手
+            self.acceptor
+                .send_reset(packet.get_id(), buf, socket)
+                .await?;
+            Err(SrError::NotConnected)?
+        }
+        if packet.seq_width() != self.buffer.width() {
+            Err(SrError::SeqWidthMismatch {
+                expect: self.buffer.width(),
+                actual: packet.seq_width(),
+            })?
+        }
         let packet_id = packet.get_id();
         // the packet id is in the windows
         match self.buffer.insert(
@@ -42,44 +109,117 @@ impl SelectResendReceiver {
                 // recv packet ok, update ack
             }
             Err(_) => {
-                // packet id mismatch , send last ack
+                // packet id out of windows, the sack tell sender what we have
             }
         }
-        self.send_ack(buf, socket, packet_id).await?;
         // slide windows
         let mut vec = Vec::new();
-        self.buffer.slide_windows().into_iter().for_each(
-            |RecvWrap { split, packet }| match split {
-                PackSplit::End => vec.push({
-                    let mut v = std::mem::take(&mut self.local_buf);
-                    v.extend(packet);
-                    v
-                }),
-                PackSplit::Follow => self.local_buf.extend(packet),
-            },
-        );
+        for RecvWrap { split, packet } in self.buffer.slide_windows() {
+            self.last_nak = None;
+            match self.reassembler.push(split, packet) {
+                Ok(msg) => vec.extend(msg),
+                Err(err) => eprintln!("Drop Message {err}"),
+            }
+        }
+        // 本次完成的消息随后进入输出队列
+        self.send_window(buf, socket, room.saturating_sub(vec.len()))
+            .await?;
+        // 窗口起点之后已有 packet 到达，起点处出现缺失
+        if !self.buffer.received_ranges().is_empty() {
+            self.send_nak(buf, socket).await?;
+        }
 
         Ok(vec)
     }
 
-    pub async fn send_ack(&self, buf: &mut Vec<u8>, socket: &UdpSocket, ack: u8) -> io::Result<()> {
-        let ack = Ack::new_ack(ack);
+    /// 按照输出队列的剩余空间 `room` 通告接收窗口
+    pub async fn send_window(
+        &self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        room: usize,
+    ) -> io::Result<()> {
+        let window = self
+            .buffer
+            .remain()
+            .min(room.try_into().unwrap_or(u32::MAX));
+        self.send_sack(buf, socket, window).await
+    }
+
+    /// 发送当前接收窗口的 SACK
+    ///
+    /// 累计确认号为窗口起点的前一个序号，其余已收到的 packet 以区间形式附带
+    pub async fn send_sack(
+        &self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        window: u32,
+    ) -> io::Result<()> {
+        let width = self.buffer.width();
+        let sack = Sack {
+            cumulative: width.sub(self.buffer.offset(), 1),
+            ranges: self.buffer.received_ranges(),
+            window,
+        };
+        let packet = Packet::new_sack(&sack, width).with_checksum(self.checksum);
 
         // write
         buf.clear();
-        let size = ack.write(buf)?;
-        let ack_packet = &buf[0..size];
+        let size = packet.write(buf)?;
+        let sack_packet = &buf[0..size];
 
         // send
-        socket.send_to(ack_packet, self.origin).await?;
+        socket.send_to(sack_packet, self.origin).await?;
         println!(
-            "Sending Ack [{}] to Socket {}",
-            ack.get_ack_num(),
-            self.origin
+            "Sending Sack [{}] {:?} window [{}] to Socket {}",
+            sack.cumulative, sack.ranges, sack.window, self.origin
         );
 
         Ok(())
     }
+
+    /// 处理 Leave，发送端的数据全部按序收到后确认并返回 true，接收端随后结束
+    pub async fn recv_leave(
+        &mut self,
+        leave_id: u32,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> Result<bool, SrError> {
+        let expect = self.buffer.offset();
+        Ok(self
+            .acceptor
+            .recv_leave(leave_id, expect, buf, socket)
+            .await?)
+    }
+
+    /// 回复发送端的 Ping
+    pub async fn send_pong(
+        &self,
+        ping_id: u32,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> io::Result<()> {
+        self.acceptor.send_pong(ping_id, buf, socket).await
+    }
+
+    /// 对窗口起点发送 NAK，要求发送端立即重传
+    pub async fn send_nak(&mut self, buf: &mut Vec<u8>, socket: &UdpSocket) -> io::Result<()> {
+        let missing = self.buffer.offset();
+        if self.last_nak == Some(missing) {
+            return Ok(());
+        }
+        let nak = Nak::new_nak(missing)
+            .with_seq_width(self.buffer.width())
+            .with_checksum(self.checksum);
+
+        buf.clear();
+        let size = nak.write(buf)?;
+        socket.send_to(&buf[0..size], self.origin).await?;
+        println!("Sending Nak [{missing}] to Socket {}", self.origin);
+        self.last_nak = Some(missing);
+
+        Ok(())
+    }
 }
 
 struct RecvWrap {
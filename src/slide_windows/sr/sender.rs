@@ -1,54 +1,472 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{collections::VecDeque, net::SocketAddr, time::Instant};
 
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 
 use crate::{
-    cycle_buffer::CycleBuffer,
+    cycle_buffer::{CbError, CycleBuffer},
     fake_udp::UdpSocket,
-    packet::Packet,
-    slide_windows::{Timer, TIMEOUT_MS},
+    packet::{
+        flags::{PackSplit, PacketType},
+        handshake::{ArqMode, Handshake, HandshakeStage},
+        sack::Sack,
+        Packet,
+    },
+    slide_windows::{
+        close::Closing,
+        config::TransportConfig,
+        congestion::{CongestionController, CongestionKind, Loss},
+        connect::{Connector, HandshakeError},
+        keepalive::Liveness,
+        retry::RetryPolicy,
+        rtt::RttEstimator,
+        segment_count, split_segments, Message, StatePacket, Timer,
+    },
+    verify::ChecksumKind,
 };
 
-use super::{SrError, MAX_WINDOWS_SIZE};
+use super::SrError;
 
 pub struct SelectResendSender {
     target: SocketAddr,
-    buffer: CycleBuffer<MAX_WINDOWS_SIZE, (Timer, Packet)>,
+    buffer: CycleBuffer<(Timer, StatePacket)>,
+    /// 单个 packet body 的最大长度
+    max_segment: usize,
+    checksum: ChecksumKind,
+    connector: Connector,
+    /// 握手协商后的窗口大小
+    window: u32,
+    /// 对端最近一次通告的接收窗口
+    peer_window: u32,
+    congestion: CongestionKind,
+    controller: Box<dyn CongestionController>,
+    /// 零窗口时的探测定时器
+    probe: Option<Timer>,
+    /// 等待窗口空余的消息
+    pending: VecDeque<Message>,
+    closing: Option<Closing>,
+    liveness: Liveness,
+    retry: RetryPolicy,
+    rtt: RttEstimator,
 }
 
 impl SelectResendSender {
-    pub fn new(target: SocketAddr) -> Self {
+    /// `config` 需要先通过 [`TransportConfig::validate`] 校验
+    pub fn new(target: SocketAddr, config: &TransportConfig) -> Self {
+        assert!(config.max_segment > 0, "max segment size must > 0");
         Self {
             target,
-            buffer: CycleBuffer::new(),
+            buffer: CycleBuffer::new(config.window, config.seq_width),
+            max_segment: config.max_segment,
+            checksum: config.checksum,
+            connector: Connector::new(
+                ArqMode::SelectResend,
+                config.window,
+                config.seq_width,
+                config.checksum,
+            ),
+            window: config.window,
+            peer_window: config.window,
+            congestion: config.congestion,
+            controller: config.congestion.controller(config.window),
+            probe: None,
+            pending: VecDeque::new(),
+            closing: None,
+            liveness: Liveness::new(config.keepalive),
+            retry: config.retry,
+            rtt: RttEstimator::new(config.initial_rto),
         }
     }
 
+    /// 当前的 RTT 估计
+    pub fn rtt(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
+    /// 收到对端的任意 packet
+    pub fn on_peer_packet(&mut self) {
+        self.liveness.on_recv();
+    }
+
+    /// 定期检查对端状态，空闲时发送 Ping，对端不可达时停止全部定时器
+    pub async fn keepalive(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+    ) -> Result<(), SrError> {
+        let ping = match self
+            .liveness
+            .tick(Instant::now(), self.target, self.buffer.width())
+        {
+            Ok(ping) => ping,
+            Err(err) => {
+                self.abort();
+                Err(err)?
+            }
+        };
+
+        if let Some(ping) = ping {
+            let ping = ping.with_checksum(self.checksum);
+            buf.clear();
+            let size = ping.write(buf)?;
+            socket.send_to(&buf[0..size], self.target).await?;
+            println!("Send Ping [{}]", ping.get_id());
+        }
+
+        Ok(())
+    }
+
+    /// 放弃连接，停止全部定时器并丢弃窗口内、发送队列中与握手期间缓存的消息
+    ///
+    /// 这些消息的送达通知随之返回 `Undelivered`；关闭流程无法完成，等待方同样收到通知失败
+    fn abort(&mut self) {
+        // 丢弃缓冲区内的 packet 同时停止它们的定时器
+        let top = self.buffer.top();
+        self.buffer.reset(top);
+        self.probe = None;
+        self.connector.stop_timer();
+        self.connector.clear_pending();
+        self.pending.clear();
+        self.closing = None;
+    }
+
+    /// Reset 携带接收端拒绝的 packet 序号，只接受最近一个窗口内发出的序号，
+    /// 避免延迟到达或伪造的 Reset 中断当前连接
+    fn accepts_reset(&self, seq: u32) -> bool {
+        let distance = self.buffer.width().sub(self.buffer.top(), seq);
+        (1..=self.window).contains(&distance)
+    }
+
+    /// 开始关闭连接，窗口内的 packet 全部确认后发送 Leave，关闭完成时通知 `notify`
+    pub fn close(&mut self, notify: oneshot::Sender<()>) {
+        let mut closing = Closing::new(notify);
+        if !self.connector.is_established() && !self.connector.has_pending() {
+            // 连接尚未建立，没有需要等待的数据
+            closing.finish();
+        }
+        self.closing = Some(closing);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closing.as_ref().is_some_and(Closing::is_closed)
+    }
+
+    /// 关闭中、连接已建立且窗口已清空时发送 Leave
+    pub async fn poll_close(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<u32>,
+    ) -> Result<(), SrError> {
+        // 连接建立前缓存的消息还没有发送
+        if !self.connector.is_established() {
+            return Ok(());
+        }
+        let closing = match self.closing.as_mut() {
+            Some(closing)
+                if closing.need_leave() && self.buffer.is_empty() && self.pending.is_empty() =>
+            {
+                closing
+            }
+            _ => return Ok(()),
+        };
+        let leave_id = self.buffer.top();
+        let leave = match closing.leave(leave_id, self.buffer.width(), self.checksum) {
+            Some(leave) => leave,
+            None => return Ok(()),
+        };
+
+        buf.clear();
+        let size = leave.write(buf)?;
+        socket.send_to(&buf[0..size], self.target).await?;
+        println!("Send Leave [{leave_id}]");
+
+        let (timer, timeout) = Timer::start(self.rtt.rto());
+        closing.set_timer(timer);
+        timeout.need_resend_do(async move {
+            eprintln!("waiting Leave ACK timeout, resend");
+            timeout_send.send(leave_id).await.ok();
+        });
+
+        Ok(())
+    }
+
+    /// 发送 SYN 发起连接，超时后以初始序号触发重传
+    pub async fn connect(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<u32>,
+    ) -> Result<(), SrError> {
+        let isn = self.connector.isn();
+        let syn = self.connector.syn();
+        buf.clear();
+        let size = syn.write(buf)?;
+        socket.send_to(&buf[0..size], self.target).await?;
+        println!("Send SYN [{isn}]");
+
+        let (timer, timeout) = Timer::start(self.rtt.rto());
+        self.connector.set_timer(timer);
+        timeout.need_resend_do(async move {
+            eprintln!("waiting SYN-ACK timeout, resend");
+            timeout_send.send(isn).await.ok();
+        });
+
+        Ok(())
+    }
+
+    /// 处理接收端回复的 SYN-ACK 与 Reset
+    pub async fn recv_handshake(
+        &mut self,
+        handshake: Handshake,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<u32>,
+    ) -> Result<(), SrError> {
+        match handshake.stage {
+            HandshakeStage::SynAck => {
+                let established = match self.connector.on_syn_ack(&handshake) {
+                    // 对端仍保留着旧连接，要求其结束旧连接，之后重传的 SYN 即可建立新连接
+                    Err(HandshakeError::Challenged { current }) => {
+                        eprintln!("Peer still holds connection isn [{current}], abort it");
+                        let abort = self.connector.abort(current);
+                        buf.clear();
+                        let size = abort.write(buf)?;
+                        socket.send_to(&buf[0..size], self.target).await?;
+                        return Ok(());
+                    }
+                    result => result?,
+                };
+                let ack = self.connector.ack();
+                buf.clear();
+                let size = ack.write(buf)?;
+                socket.send_to(&buf[0..size], self.target).await?;
+
+                if let Some(established) = established {
+                    println!(
+                        "Connection established isn [{}] window [{}]",
+                        established.isn, established.window
+                    );
+                    self.buffer.reset(established.isn);
+                    self.window = established.window;
+                    self.peer_window = established.window;
+                    self.controller = self.congestion.controller(established.window);
+                    // 连接建立前缓存的消息，即使已经开始关闭也需要发送
+                    self.pending.extend(established.pending);
+                    self.poll_pending(buf, socket, timeout_send).await?;
+                }
+            }
+            // 接收端丢失了连接，窗口为空时重新握手，否则未确认的数据已经无法送达
+            HandshakeStage::Reset
+                if self.connector.is_established() && self.accepts_reset(handshake.isn) =>
+            {
+                let dropped = self.in_flight();
+                if dropped > 0 {
+                    self.abort();
+                    Err(SrError::ConnectionReset { dropped })?
+                }
+                // 已确认的 packet 同样丢弃
+                self.buffer.reset(0);
+                self.probe = None;
+                eprintln!("Connection reset by peer, reconnect");
+                self.connector.restart();
+                self.connect(buf, socket, timeout_send).await?;
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// 发送一条消息，超过最大分段长度的消息会被切分为多个 packet
+    ///
+    /// 窗口剩余空间不足时先发送能够容纳的分段，其余分段在发送队列中等待
     pub async fn send(
+        &mut self,
+        buf: &mut Vec<u8>,
+        msg: Message,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<u32>,
+    ) -> Result<(), SrError> {
+        if self.closing.is_some() {
+            Err(SrError::Closed)?
+        }
+        if !self.connector.is_established() {
+            self.connector.queue(msg);
+            return Ok(());
+        }
+        self.pending.push_back(msg);
+        self.poll_pending(buf, socket, timeout_send).await
+    }
+
+    /// 按顺序发送窗口能够容纳的等待中的消息
+    pub async fn poll_pending(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<u32>,
+    ) -> Result<(), SrError> {
+        if !self.connector.is_established() {
+            return Ok(());
+        }
+        loop {
+            let room = self.send_room() as usize;
+            let msg = match self.pending.front_mut() {
+                Some(msg) if room > 0 => msg,
+                _ => break,
+            };
+            if segment_count(msg.body.len(), self.max_segment) > room {
+                // 窗口只能容纳部分分段，其余分段与送达通知留在发送队列开头
+                let head = msg.split_head(room, self.max_segment);
+                for body in head.chunks(self.max_segment) {
+                    self.send_segment(
+                        buf,
+                        body.to_vec(),
+                        PackSplit::Follow,
+                        None,
+                        socket,
+                        timeout_send.clone(),
+                    )
+                    .await?;
+                }
+                break;
+            }
+            let msg = self.pending.pop_front().expect("pending message exists");
+            self.send_message(buf, msg, socket, timeout_send.clone())
+                .await?;
+        }
+        // 对端窗口为零，等待超时后发送窗口探测
+        if self.need_probe() && self.probe.is_none() {
+            self.start_probe(timeout_send);
+        }
+        Ok(())
+    }
+
+    /// 窗口、对端通告窗口与拥塞窗口允许继续发送的 packet 数量
+    ///
+    /// 对端通告的是接收窗口的空位，只与尚未被确认的 packet 比较
+    fn send_room(&self) -> u32 {
+        let peer_room = self
+            .peer_window
+            .min(self.controller.window())
+            .saturating_sub(self.in_flight());
+        (self.window - self.buffer.len()).min(peer_room)
+    }
+
+    /// 已发送但尚未被确认的 packet 数量，已确认的 packet 不会从缓冲区取出
+    fn in_flight(&self) -> u32 {
+        let width = self.buffer.width();
+        (0..self.buffer.len())
+            .filter(|&distance| {
+                self.buffer
+                    .get(width.add(self.buffer.button(), distance))
+                    .is_some()
+            })
+            .count() as u32
+    }
+
+    /// 对端通告零窗口且没有在途 packet 时，只能通过探测得知窗口重新打开
+    fn need_probe(&self) -> bool {
+        self.peer_window == 0 && self.buffer.is_empty() && !self.pending.is_empty()
+    }
+
+    /// 窗口探测使用已确认的序号，超时后以该序号触发
+    fn probe_id(&self) -> u32 {
+        self.buffer.width().sub(self.buffer.top(), 1)
+    }
+
+    fn start_probe(&mut self, timeout_send: mpsc::Sender<u32>) {
+        let probe_id = self.probe_id();
+        let (timer, timeout) = Timer::start(self.rtt.rto());
+        self.probe = Some(timer);
+        timeout.need_resend_do(async move {
+            eprintln!("waiting window update timeout, probe");
+            timeout_send.send(probe_id).await.ok();
+        });
+    }
+
+    /// 发送窗口探测，使用已确认的序号与空 body，接收端只会回复带有最新窗口的 SACK
+    async fn probe_window(
+        &mut self,
+        buf: &mut Vec<u8>,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<u32>,
+    ) -> Result<(), SrError> {
+        if !self.need_probe() {
+            self.probe = None;
+            return Ok(());
+        }
+        let probe_id = self.probe_id();
+        let probe = Packet::new_data(probe_id, Vec::new())
+            .with_seq_width(self.buffer.width())
+            .with_checksum(self.checksum);
+        buf.clear();
+        let size = probe.write(buf)?;
+        socket.send_to(&buf[0..size], self.target).await?;
+        println!("Send Window Probe [{probe_id}]");
+
+        self.start_probe(timeout_send);
+        Ok(())
+    }
+
+    async fn send_message(
+        &mut self,
+        buf: &mut Vec<u8>,
+        msg: Message,
+        socket: &UdpSocket,
+        timeout_send: mpsc::Sender<u32>,
+    ) -> Result<(), SrError> {
+        let segments = split_segments(msg.body, self.max_segment);
+        if segments.len() > (self.window - self.buffer.len()) as usize {
+            Err(CbError::BufferFilled)?
+        }
+
+        // 送达通知跟随最后一个分段
+        let mut delivery = msg.delivery;
+        for (split, body) in segments {
+            let delivery = match split {
+                PackSplit::End => delivery.take(),
+                PackSplit::Follow => None,
+            };
+            self.send_segment(buf, body, split, delivery, socket, timeout_send.clone())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn send_segment(
         &mut self,
         buf: &mut Vec<u8>,
         body: Vec<u8>,
+        split: PackSplit,
+        delivery: Option<oneshot::Sender<()>>,
         socket: &UdpSocket,
-        timeout_send: mpsc::Sender<u8>,
+        timeout_send: mpsc::Sender<u32>,
     ) -> Result<(), SrError> {
         let this_id = self.buffer.top();
         // 封装包
-        let packet = Packet::new_data(this_id, body);
+        let packet = Packet::new(this_id, body, PacketType::Data, split)
+            .with_seq_width(self.buffer.width())
+            .with_checksum(self.checksum);
         buf.clear();
         let size = packet.write(buf)?;
         let send_packet = &buf[0..size];
 
-        let (timer, starter, timeout) = Timer::later_start(Duration::from_millis(TIMEOUT_MS));
+        let (timer, starter, timeout) = Timer::later_start(self.retry.backoff(self.rtt.rto(), 0));
         timeout.need_resend_do(async move {
             eprintln!("waiting timeout, resend");
             timeout_send.send(this_id).await.ok();
         });
         // packet 加入缓冲区
-        self.buffer.push((timer, packet))?;
+        self.buffer.push((
+            timer,
+            StatePacket::new_waiting(packet).with_delivery(delivery),
+        ))?;
         println!("Add Packet to Buffer now size : [{}]", self.buffer.len());
 
         // send packet
         socket.send_to(send_packet, self.target).await?;
+        self.controller.on_send(Instant::now(), 1);
         println!("Send Packet done [{}]", this_id);
 
         // start timer
@@ -57,26 +475,109 @@ impl SelectResendSender {
         Ok(())
     }
 
-    pub async fn recv_ack(&mut self, ack: u8) {
-        if let Some((timer, _)) = self.buffer.get(ack) {
+    /// `window` 为 ACK 携带的对端接收窗口
+    pub async fn recv_ack(&mut self, ack: u32, window: Option<u32>) {
+        if let Some(window) = window {
+            self.peer_window = window;
+        }
+        if let Some(closing) = self.closing.as_mut() {
+            closing.on_ack(ack);
+        }
+        self.mark_done(ack);
+        self.buffer.slide_buff();
+    }
+
+    /// 一次 SACK 确认累计确认号之前以及各个区间内的全部 packet
+    pub async fn recv_sack(&mut self, sack: &Sack) {
+        self.peer_window = sack.window;
+        let width = self.buffer.width();
+        let button = self.buffer.button();
+        // 累计确认号落后于窗口时视为没有新的累计确认
+        let cumulative = width
+            .in_range(sack.cumulative, button, self.buffer.len())
+            .then_some((button, sack.cumulative));
+        let ranges = cumulative
+            .into_iter()
+            .chain(sack.ranges.iter().copied())
+            .collect::<Vec<_>>();
+
+        // 逐个检查窗口内的 packet 是否被任一区间覆盖
+        for distance in 0..self.buffer.len() {
+            let id = width.add(button, distance);
+            if ranges.iter().any(|&(start, end)| {
+                width.in_range(id, start, width.sub(end, start).saturating_add(1))
+            }) {
+                self.mark_done(id);
+            }
+        }
+
+        self.buffer.slide_buff();
+    }
+
+    fn mark_done(&mut self, packet_id: u32) {
+        if let Some((timer, packet)) = self.buffer.get_mut(packet_id) {
             // target ack is on waiting, recv it ack ,can stop timer;
             timer.stop();
+            let rtt = packet.rtt_sample();
+            if let Some(rtt) = rtt {
+                self.rtt.on_sample(rtt);
+            }
+            self.controller.on_ack(Instant::now(), 1, rtt);
+            packet.recv_ack();
         }
 
-        self.buffer.buffer_down(ack);
-        self.buffer.slide_buff();
+        self.buffer.buffer_down(packet_id);
     }
 
+    /// 重传单个 packet，`loss` 为触发重传的原因，用于调整拥塞窗口
     pub async fn select_resend(
         &mut self,
-        packet_id: u8,
+        packet_id: u32,
+        loss: Loss,
         buf: &mut Vec<u8>,
         socket: &UdpSocket,
-        timeout_send: mpsc::Sender<u8>,
+        timeout_send: mpsc::Sender<u32>,
     ) -> Result<(), SrError> {
+        // 连接未建立时超时重传 SYN
+        if !self.connector.is_established() {
+            if packet_id == self.connector.isn() {
+                self.connect(buf, socket, timeout_send).await?;
+            }
+            return Ok(());
+        }
+        // Leave 超时，由 poll_close 重传
+        if let Some(closing) = self.closing.as_mut() {
+            if closing.leave_seq() == Some(packet_id) {
+                closing.on_timeout();
+                return Ok(());
+            }
+        }
+        // 窗口已清空，超时来自窗口探测
+        if self.buffer.is_empty() && self.probe.is_some() && packet_id == self.probe_id() {
+            return self.probe_window(buf, socket, timeout_send).await;
+        }
+        // 快速重传说明链路仍然可达，只有超时计入重传次数
+        let retries = match (self.buffer.get_mut(packet_id), loss) {
+            (Some((_, packet)), Loss::Timeout) => packet.retry(),
+            (Some((_, packet)), Loss::Fast) => {
+                packet.sent_at = None;
+                packet.retries
+            }
+            (None, _) => return Ok(()),
+        };
+        if self.retry.is_exhausted(retries) {
+            self.abort();
+            Err(SrError::RetriesExhausted {
+                packet_id,
+                retries: retries - 1,
+            })?
+        }
+        let in_flight = self.in_flight();
+        self.controller.on_loss(Instant::now(), loss, in_flight, 1);
         if let Some((src_timer, packet)) = self.buffer.get_mut(packet_id) {
             src_timer.stop();
-            let (timer, starter, timeout) = Timer::later_start(Duration::from_millis(TIMEOUT_MS));
+            let (timer, starter, timeout) =
+                Timer::later_start(self.retry.backoff(self.rtt.rto(), retries));
             *src_timer = timer;
             timeout.need_resend_do(async move {
                 eprintln!("waiting timeout , resend");
@@ -85,12 +586,13 @@ impl SelectResendSender {
 
             //write packet
             buf.clear();
-            let size = packet.write(buf)?;
+            let size = packet.pkg.write(buf)?;
             let send_packet = &buf[0..size];
 
             // send packet
             socket.send_to(send_packet, self.target).await?;
-            println!("Resend Packet [{}] done", packet.get_id());
+            self.controller.on_send(Instant::now(), 1);
+            println!("Resend Packet [{packet_id}] done, retries {retries}");
             // start timer
             starter.start();
         }
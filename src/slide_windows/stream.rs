@@ -0,0 +1,747 @@
+//! 面向连接的接口
+//!
+//! [`RdtListener`] 与 [`RdtStream`] 持有 socket 并在后台运行分发循环，
+//! 每个对端对应一个连接，连接内同时运行 [`Transport`] 的发送端与接收端
+//!
+//! [`RdtStream`] 实现了 [`AsyncRead`] 与 [`AsyncWrite`]，可以当作字节流使用，
+//! 每次写入作为一条消息发送，读取时消息边界不再保留
+
+use std::{
+    collections::BTreeMap,
+    future::{poll_fn, Future},
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{future::BoxFuture, ready};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::ToSocketAddrs,
+    sync::{
+        mpsc::{self, error::SendError, OwnedPermit},
+        oneshot, AcquireError, OwnedSemaphorePermit, Semaphore,
+    },
+};
+
+use crate::{
+    fake_udp::UdpSocket,
+    packet::{handshake::HandshakeStage, PacketRef},
+};
+
+use super::{
+    config::{ConfigError, TransportConfig},
+    transport::Transport,
+    Delivery, Message, RecvMsg, MAX_BUFF_SIZE,
+};
+
+/// 等待 [`RdtListener::accept`] 的连接数量上限，超出时丢弃新的 SYN
+pub const ACCEPT_BACKLOG: usize = 32;
+
+/// 分发循环中的一个对端
+struct Peer<T: Transport> {
+    sender: mpsc::Sender<T::SenderMsg>,
+    receiver: mpsc::Sender<RecvMsg>,
+    /// 发送端因无法恢复的错误结束
+    failed: oneshot::Receiver<()>,
+}
+
+impl<T: Transport> Peer<T> {
+    /// 发送端与接收端均已结束，或者发送端出错后连接已经无法继续
+    ///
+    /// 移除连接时关闭接收端的通道，接收端随之结束
+    fn is_closed(&mut self) -> bool {
+        self.sender.is_closed() && (self.receiver.is_closed() || self.failed.try_recv().is_ok())
+    }
+}
+
+/// 与 `peer` 之间的连接，启动发送端与接收端
+fn open<T: Transport + 'static>(
+    socket: &Arc<UdpSocket>,
+    local: SocketAddr,
+    peer: SocketAddr,
+    config: TransportConfig,
+) -> Result<(Peer<T>, RdtStream<T>), ConfigError> {
+    let (timeout_send, mut timeouts) = mpsc::channel(16);
+    let (fatal_send, fatal_recv) = oneshot::channel();
+    let sender = T::start_send_peer(Arc::clone(socket), peer, timeout_send, config, fatal_send)?;
+
+    let (output_send, output) = mpsc::channel(64);
+    let receiver = T::start_receive_peer(Arc::clone(socket), peer, output_send, config)?;
+
+    // 定时器事件转发给发送端，发送端结束后随之退出
+    let forward = sender.clone();
+    tokio::spawn(async move {
+        while let Some(event) = timeouts.recv().await {
+            if forward.send(T::timeout(event)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // 发送端的错误转告应用层，同时通知分发循环移除连接
+    let (error_send, fatal) = oneshot::channel();
+    let (failed_send, failed) = oneshot::channel();
+    tokio::spawn(async move {
+        if let Ok(err) = fatal_recv.await {
+            error_send.send(err).ok();
+            failed_send.send(()).ok();
+        }
+    });
+
+    let stream = RdtStream::new(local, peer, sender.clone(), output, fatal, &config);
+    let connection = Peer {
+        sender,
+        receiver,
+        failed,
+    };
+    Ok((connection, stream))
+}
+
+/// 接收 socket 上的 packet 并按来源分发给对应的连接
+///
+/// `incoming` 不为 `None` 时，来自未知对端的 SYN 会建立新连接；
+/// 没有存活的连接且不再接受新连接时退出。
+/// 所有连接共用同一个循环，连接的队列已满时直接丢弃 packet，由重传恢复，
+/// 避免一个没有读取的连接阻塞其他连接
+async fn dispatch<T: Transport + 'static>(
+    socket: Arc<UdpSocket>,
+    config: TransportConfig,
+    mut peers: BTreeMap<SocketAddr, Peer<T>>,
+    incoming: Option<mpsc::Sender<RdtStream<T>>>,
+) {
+    let local = match socket.local_addr() {
+        Ok(local) => local,
+        Err(err) => {
+            eprintln!("Error 发生 {err}");
+            return;
+        }
+    };
+    let mut buf = vec![0u8; MAX_BUFF_SIZE];
+    loop {
+        peers.retain(|_, peer| !peer.is_closed());
+        let listening = incoming
+            .as_ref()
+            .is_some_and(|incoming| !incoming.is_closed());
+        if peers.is_empty() && !listening {
+            break;
+        }
+
+        let (size, origin) = tokio::select! {
+            recv = socket.recv_from(&mut buf) => match recv {
+                Ok(recv) => recv,
+                Err(err) => {
+                    eprintln!("Error 发生 {err}");
+                    continue;
+                }
+            },
+            // 定期检查连接是否全部结束
+            _ = tokio::time::sleep(config.keepalive.tick_period()) => continue,
+        };
+        let packet = PacketRef::read(&buf[0..size]);
+
+        if let Some(peer) = peers.get(&origin) {
+            let full = match packet.as_ref().ok().and_then(T::feedback) {
+                Some(msg) => peer.sender.try_send(msg).is_err(),
+                None => {
+                    let packet = RecvMsg(packet.map(|packet| packet.to_owned()));
+                    peer.receiver.try_send(packet).is_err()
+                }
+            };
+            if full {
+                eprintln!("Connection {origin} busy, drop packet");
+            }
+            continue;
+        }
+
+        // 只有 SYN 可以建立新连接
+        let incoming = match incoming.as_ref() {
+            Some(incoming) if listening => incoming,
+            _ => continue,
+        };
+        let is_syn = packet
+            .as_ref()
+            .ok()
+            .and_then(PacketRef::as_handshake)
+            .is_some_and(|handshake| handshake.stage == HandshakeStage::Syn);
+        if !is_syn {
+            continue;
+        }
+        let permit = match incoming.try_reserve() {
+            Ok(permit) => permit,
+            Err(_) => {
+                eprintln!("Accept backlog filled, drop SYN from {origin}");
+                continue;
+            }
+        };
+        let (peer, stream) = match open::<T>(&socket, local, origin, config) {
+            Ok(open) => open,
+            Err(err) => {
+                eprintln!("Error 发生 {err}");
+                continue;
+            }
+        };
+        let packet = RecvMsg(packet.map(|packet| packet.to_owned()));
+        peer.receiver.try_send(packet).ok();
+        println!("Accept connection from {origin}");
+        permit.send(stream);
+        peers.insert(origin, peer);
+    }
+}
+
+/// 监听本地地址，接受对端发起的连接
+pub struct RdtListener<T: Transport> {
+    local: SocketAddr,
+    incoming: mpsc::Receiver<RdtStream<T>>,
+}
+
+impl<T: Transport + 'static> RdtListener<T> {
+    pub async fn bind(addr: impl ToSocketAddrs, config: TransportConfig) -> Result<Self, T::Error> {
+        config.validate(T::MODE)?;
+        let socket = Arc::new(UdpSocket::bind(addr).await?);
+        let local = socket.local_addr()?;
+
+        let (incoming_send, incoming) = mpsc::channel(ACCEPT_BACKLOG);
+        tokio::spawn(dispatch(
+            socket,
+            config,
+            BTreeMap::new(),
+            Some(incoming_send),
+        ));
+
+        Ok(Self { local, incoming })
+    }
+
+    /// 等待下一个对端发起的连接
+    pub async fn accept(&mut self) -> Result<RdtStream<T>, T::Error> {
+        match self.incoming.recv().await {
+            Some(stream) => Ok(stream),
+            None => Err(io::Error::other("listener stopped"))?,
+        }
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local
+    }
+}
+
+type Reserve<M> = BoxFuture<'static, Result<OwnedPermit<M>, SendError<()>>>;
+type Acquire = BoxFuture<'static, Result<OwnedSemaphorePermit, AcquireError>>;
+
+/// 与一个对端之间的连接
+///
+/// 丢弃时会在后台关闭连接，需要等待关闭完成时使用 [`RdtStream::close`]
+pub struct RdtStream<T: Transport> {
+    local: SocketAddr,
+    peer: SocketAddr,
+    sender: mpsc::Sender<T::SenderMsg>,
+    output: mpsc::Receiver<Vec<u8>>,
+    /// 发送端的错误通知，取出错误或发送端正常结束后为 `None`
+    fatal: Option<oneshot::Receiver<T::Error>>,
+    closing: bool,
+    /// 单次写入的最大长度，不能超过对端的重组消息长度上限与窗口大小
+    max_write: usize,
+    /// 尚未读取完的消息
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    /// 发送队列的名额，消息进入窗口后归还
+    queue: Arc<Semaphore>,
+    /// 等待发送队列的名额
+    acquire: Option<Acquire>,
+    /// 已获得但尚未使用的名额
+    queued: Option<OwnedSemaphorePermit>,
+    /// 等待发送端通道的空位
+    reserve: Option<Reserve<T::SenderMsg>>,
+    /// 等待关闭完成
+    shutdown: Option<oneshot::Receiver<()>>,
+}
+
+impl<T: Transport + 'static> RdtStream<T> {
+    /// 绑定任意本地端口并连接 `peer`
+    ///
+    /// 握手在后台完成，连接建立前发送的消息会被缓存
+    pub async fn connect(peer: SocketAddr, config: TransportConfig) -> Result<Self, T::Error> {
+        let local = match peer {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        Self::connect_from(local, peer, config).await
+    }
+
+    /// 绑定 `local` 并连接 `peer`
+    pub async fn connect_from(
+        local: SocketAddr,
+        peer: SocketAddr,
+        config: TransportConfig,
+    ) -> Result<Self, T::Error> {
+        config.validate(T::MODE)?;
+        let socket = Arc::new(UdpSocket::bind(local).await?);
+        let local = socket.local_addr()?;
+
+        let (connection, stream) = open::<T>(&socket, local, peer, config)?;
+        let peers = BTreeMap::from([(peer, connection)]);
+        tokio::spawn(dispatch(socket, config, peers, None));
+
+        Ok(stream)
+    }
+}
+
+impl<T: Transport> RdtStream<T> {
+    fn new(
+        local: SocketAddr,
+        peer: SocketAddr,
+        sender: mpsc::Sender<T::SenderMsg>,
+        output: mpsc::Receiver<Vec<u8>>,
+        fatal: oneshot::Receiver<T::Error>,
+        config: &TransportConfig,
+    ) -> Self {
+        Self {
+            local,
+            peer,
+            sender,
+            output,
+            fatal: Some(fatal),
+            closing: false,
+            max_write: config
+                .max_message
+                .min(config.max_segment * config.window as usize),
+            read_buf: Vec::new(),
+            read_pos: 0,
+            queue: Arc::new(Semaphore::new(config.max_pending)),
+            acquire: None,
+            queued: None,
+            reserve: None,
+            shutdown: None,
+        }
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// 发送一条消息，返回的 [`Delivery`] 在消息的全部分段确认后完成
+    ///
+    /// 发送队列已满时等待，直到有消息进入窗口
+    pub async fn send(&mut self, body: Vec<u8>) -> Result<Delivery, T::Error> {
+        let permit = Arc::clone(&self.queue)
+            .acquire_owned()
+            .await
+            .expect("send queue never closed");
+        let (msg, delivery) = Message::with_delivery(body);
+        let msg = msg.with_permit(permit);
+        if self.sender.send(T::message(msg)).await.is_err() {
+            let err = poll_fn(|cx| self.poll_fatal(cx)).await;
+            Err(err.unwrap_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe).into()))?
+        }
+        Ok(delivery)
+    }
+
+    /// 接收对端的下一条消息，对端关闭连接后返回 `None`
+    ///
+    /// 通过 [`AsyncRead`] 读取了一部分的消息会先返回剩余的部分
+    pub async fn recv(&mut self) -> Option<Vec<u8>> {
+        if self.read_pos < self.read_buf.len() {
+            let rest = self.read_buf.split_off(self.read_pos);
+            self.read_buf.clear();
+            self.read_pos = 0;
+            return Some(rest);
+        }
+        self.output.recv().await
+    }
+
+    /// 发送完全部数据后关闭连接，等待对端确认
+    pub async fn close(mut self) -> Result<(), T::Error> {
+        self.closing = true;
+        let (notify, wait) = oneshot::channel();
+        if self.sender.send(T::close(notify)).await.is_ok() && wait.await.is_ok() {
+            return Ok(());
+        }
+        // 发送端已经结束，等待错误通知
+        match poll_fn(|cx| self.poll_fatal(cx)).await {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// 等待发送端结束，出错时返回错误，发送端正常结束或错误已经取出时返回 `None`
+    fn poll_fatal(&mut self, cx: &mut Context<'_>) -> Poll<Option<T::Error>> {
+        let Some(fatal) = self.fatal.as_mut() else {
+            return Poll::Ready(None);
+        };
+        let err = ready!(Pin::new(fatal).poll(cx)).ok();
+        self.fatal = None;
+        Poll::Ready(err)
+    }
+
+    /// 不等待地取出发送端的错误
+    fn try_fatal(&mut self) -> Option<T::Error> {
+        let err = self.fatal.as_mut()?.try_recv().ok();
+        if err.is_some() {
+            self.fatal = None;
+        }
+        err
+    }
+
+    fn poll_acquire(&mut self, cx: &mut Context<'_>) -> Poll<OwnedSemaphorePermit> {
+        let acquire = self
+            .acquire
+            .get_or_insert_with(|| Box::pin(Arc::clone(&self.queue).acquire_owned()));
+        let permit = ready!(acquire.as_mut().poll(cx));
+        self.acquire = None;
+        Poll::Ready(permit.expect("send queue never closed"))
+    }
+
+    fn poll_reserve(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<OwnedPermit<T::SenderMsg>>> {
+        let reserve = self
+            .reserve
+            .get_or_insert_with(|| Box::pin(self.sender.clone().reserve_owned()));
+        let permit = ready!(reserve.as_mut().poll(cx));
+        self.reserve = None;
+        match permit {
+            Ok(permit) => Poll::Ready(Ok(permit)),
+            // 发送端已经结束，等待错误通知，等待期间再次轮询时通道仍然是关闭的
+            Err(_) => match ready!(self.poll_fatal(cx)) {
+                Some(err) => Poll::Ready(Err(io::Error::other(err))),
+                None => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+            },
+        }
+    }
+}
+
+impl<T: Transport> AsyncRead for RdtStream<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        // 跳过空消息，避免被当作 EOF
+        while this.read_pos == this.read_buf.len() {
+            match this.output.poll_recv(cx) {
+                Poll::Ready(Some(msg)) => {
+                    this.read_buf = msg;
+                    this.read_pos = 0;
+                }
+                // 发送端出错时连接被移除，错误先于接收端结束送达
+                Poll::Ready(None) => {
+                    return match this.try_fatal() {
+                        Some(err) => Poll::Ready(Err(io::Error::other(err))),
+                        None => Poll::Ready(Ok(())),
+                    };
+                }
+                Poll::Pending => {
+                    if let Poll::Ready(Some(err)) = this.poll_fatal(cx) {
+                        return Poll::Ready(Err(io::Error::other(err)));
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+
+        let size = buf.remaining().min(this.read_buf.len() - this.read_pos);
+        buf.put_slice(&this.read_buf[this.read_pos..this.read_pos + size]);
+        this.read_pos += size;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: Transport> AsyncWrite for RdtStream<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.closing {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        if this.queued.is_none() {
+            this.queued = Some(ready!(this.poll_acquire(cx)));
+        }
+        let permit = ready!(this.poll_reserve(cx))?;
+        let queued = this.queued.take().expect("queue permit acquired");
+        let size = buf.len().min(this.max_write);
+        let msg = Message::new(buf[0..size].to_vec()).with_permit(queued);
+        permit.send(T::message(msg));
+        Poll::Ready(Ok(size))
+    }
+
+    /// 写入的数据已经交给发送端
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// 发送完全部数据后关闭连接，等待对端确认
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.closing {
+            if let Some(err) = this.try_fatal() {
+                return Poll::Ready(Err(io::Error::other(err)));
+            }
+            let permit = match ready!(this.poll_reserve(cx)) {
+                Ok(permit) => permit,
+                // 发送端已经正常结束
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Poll::Ready(Ok(())),
+                Err(err) => return Poll::Ready(Err(err)),
+            };
+            let (notify, wait) = oneshot::channel();
+            permit.send(T::close(notify));
+            this.closing = true;
+            this.shutdown = Some(wait);
+        }
+
+        if let Some(wait) = this.shutdown.as_mut() {
+            let done = ready!(Pin::new(wait).poll(cx));
+            this.shutdown = None;
+            if done.is_ok() {
+                return Poll::Ready(Ok(()));
+            }
+        }
+        // 发送端异常结束，等待错误通知
+        match ready!(this.poll_fatal(cx)) {
+            Some(err) => Poll::Ready(Err(io::Error::other(err))),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl<T: Transport> Drop for RdtStream<T> {
+    fn drop(&mut self) {
+        if !self.closing {
+            let (notify, _) = oneshot::channel();
+            self.sender.try_send(T::close(notify)).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::SocketAddr, time::Duration};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        sync::{mpsc, oneshot},
+    };
+
+    use crate::slide_windows::{
+        config::TransportConfig,
+        gbn::{GbnError, GoBackN, SenderMsg},
+        retry::RetryPolicy,
+        sr::SelectResend,
+        transport::Transport,
+    };
+
+    use super::{Peer, RdtListener, RdtStream};
+
+    /// 不经过网络的连接，测试直接读写发送端与接收端的通道
+    struct Detached {
+        stream: RdtStream<GoBackN>,
+        sender: mpsc::Receiver<SenderMsg>,
+        output: mpsc::Sender<Vec<u8>>,
+        fatal: oneshot::Sender<GbnError>,
+    }
+
+    fn detached(config: TransportConfig) -> Detached {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let (sender_send, sender) = mpsc::channel(128);
+        let (output, output_recv) = mpsc::channel(64);
+        let (fatal, fatal_recv) = oneshot::channel();
+        let stream = RdtStream::new(addr, addr, sender_send, output_recv, fatal_recv, &config);
+        Detached {
+            stream,
+            sender,
+            output,
+            fatal,
+        }
+    }
+
+    /// fake_udp 的丢包率较高，放宽重传次数并缩短初始超时
+    fn config() -> TransportConfig {
+        TransportConfig {
+            initial_rto: Duration::from_millis(200),
+            retry: RetryPolicy {
+                max_retries: 16,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    async fn round_trip<T: Transport + 'static>() {
+        let mut listener = RdtListener::<T>::bind("127.0.0.1:0", config())
+            .await
+            .unwrap();
+        let mut client = RdtStream::<T>::connect(listener.local_addr(), config())
+            .await
+            .unwrap();
+
+        // 连接建立前发送的消息在握手完成后送达
+        let delivery = client.send(b"hello".to_vec()).await.unwrap();
+        let mut server = listener.accept().await.unwrap();
+        assert_eq!(server.peer_addr().port(), client.local_addr().port());
+        assert_eq!(server.recv().await.unwrap(), b"hello");
+        delivery.await.unwrap();
+
+        let delivery = server.send(b"world".to_vec()).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), b"world");
+        delivery.await.unwrap();
+
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connect_accept() {
+        let both = async { tokio::join!(round_trip::<GoBackN>(), round_trip::<SelectResend>()) };
+        tokio::time::timeout(Duration::from_secs(120), both)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_read_across_messages() {
+        let Detached {
+            mut stream, output, ..
+        } = detached(TransportConfig::default());
+        for msg in [vec![1, 2, 3], vec![], vec![4, 5], vec![], vec![], vec![6]] {
+            output.send(msg).await.unwrap();
+        }
+        drop(output);
+
+        // 一次读取只返回当前消息剩余的部分
+        let mut buf = [0; 2];
+        assert_eq!(stream.read(&mut buf).await.unwrap(), 2);
+        assert_eq!(buf, [1, 2]);
+        // 跨越消息边界读取，空消息不会被当作 EOF
+        let mut buf = [0; 3];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [3, 4, 5]);
+
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, [6]);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let Detached {
+            mut stream,
+            mut sender,
+            ..
+        } = detached(TransportConfig::default());
+        stream.write_all(&[1, 2, 3]).await.unwrap();
+        assert!(matches!(sender.recv().await, Some(SenderMsg::Msg(msg)) if msg.body == [1, 2, 3]));
+
+        let shutdown = tokio::spawn(async move {
+            stream.shutdown().await.unwrap();
+            stream
+        });
+        let notify = match sender.recv().await {
+            Some(SenderMsg::Close(notify)) => notify,
+            msg => panic!("expect close, got {msg:?}"),
+        };
+        // Leave 确认前保持等待
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!shutdown.is_finished());
+
+        // 发送端收到 Leave 的确认后通知关闭完成
+        notify.send(()).unwrap();
+        let mut stream = shutdown.await.unwrap();
+        let err = stream.write(&[4]).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    async fn test_write_backpressure() {
+        let Detached {
+            mut stream,
+            mut sender,
+            ..
+        } = detached(TransportConfig {
+            max_pending: 2,
+            ..Default::default()
+        });
+        stream.write_all(&[1]).await.unwrap();
+        stream.write_all(&[2]).await.unwrap();
+
+        // 发送队列的名额用完后写入等待
+        let blocked = tokio::time::timeout(Duration::from_millis(50), stream.write(&[3])).await;
+        assert!(blocked.is_err());
+
+        // 消息进入窗口后归还名额
+        let first = sender.recv().await.unwrap();
+        assert!(matches!(&first, SenderMsg::Msg(msg) if msg.body == [1]));
+        drop(first);
+        assert_eq!(stream.write(&[3]).await.unwrap(), 1);
+        assert!(matches!(sender.recv().await, Some(SenderMsg::Msg(msg)) if msg.body == [2]));
+        assert!(matches!(sender.recv().await, Some(SenderMsg::Msg(msg)) if msg.body == [3]));
+    }
+
+    #[tokio::test]
+    async fn test_fatal() {
+        let Detached {
+            mut stream,
+            sender,
+            output,
+            fatal,
+        } = detached(TransportConfig::default());
+        let read = tokio::spawn(async move {
+            let err = stream.read(&mut [0; 4]).await.unwrap_err();
+            (stream, err, output)
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!read.is_finished());
+
+        // 发送端出错后，等待中的读取返回错误
+        drop(sender);
+        fatal
+            .send(GbnError::ConnectionReset { dropped: 1 })
+            .unwrap();
+        let (_, err, _) = read.await.unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+
+        // 发送端结束后错误才送达时，关闭同样返回错误，而不是当作发送端正常结束
+        let Detached {
+            mut stream,
+            sender,
+            fatal,
+            ..
+        } = detached(TransportConfig::default());
+        drop(sender);
+        let shutdown = tokio::spawn(async move { stream.shutdown().await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!shutdown.is_finished());
+        fatal
+            .send(GbnError::ConnectionReset { dropped: 1 })
+            .unwrap();
+        let err = shutdown.await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn test_peer_failed() {
+        let (sender, sender_recv) = mpsc::channel(1);
+        let (receiver, _receiver_recv) = mpsc::channel(1);
+        let (failed_send, failed) = oneshot::channel();
+        let mut peer = Peer::<GoBackN> {
+            sender,
+            receiver,
+            failed,
+        };
+
+        // 发送端正常结束后接收端继续接收对端的数据
+        drop(sender_recv);
+        assert!(!peer.is_closed());
+        // 发送端出错后移除连接
+        failed_send.send(()).unwrap();
+        assert!(peer.is_closed());
+    }
+}
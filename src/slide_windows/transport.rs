@@ -0,0 +1,182 @@
+//! Go back N、选择重传与停等协议共用的传输接口
+//!
+//! 应用层通过 [`ArqMode`] 选择算法，使用 [`run_peer`] 同时运行发送端与接收端
+
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
+
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+
+use crate::{
+    fake_udp::UdpSocket,
+    packet::{handshake::ArqMode, PacketRef},
+};
+
+use super::{
+    config::{ConfigError, TransportConfig},
+    gbn::{GbnError, GoBackN},
+    saw::{SawError, StopAndWait},
+    sr::{SelectResend, SrError},
+    Message, RecvMsg, MAX_BUFF_SIZE,
+};
+
+/// 一种 ARQ 算法的发送端与接收端
+pub trait Transport {
+    type SenderMsg: Send + 'static;
+    /// 发送端定时器超时时产生的事件
+    type Timeout: Send + 'static;
+    type Error: std::error::Error + From<ConfigError> + From<std::io::Error> + Send + Sync + 'static;
+
+    const MODE: ArqMode;
+
+    /// 启动发送端，对端不可达等无法恢复的错误通过 `fatal` 通知
+    fn start_send_peer(
+        socket: Arc<UdpSocket>,
+        target: SocketAddr,
+        timeout_send: mpsc::Sender<Self::Timeout>,
+        config: TransportConfig,
+        fatal: oneshot::Sender<Self::Error>,
+    ) -> Result<mpsc::Sender<Self::SenderMsg>, ConfigError>;
+
+    fn start_receive_peer(
+        socket: Arc<UdpSocket>,
+        origin: SocketAddr,
+        output: mpsc::Sender<Vec<u8>>,
+        config: TransportConfig,
+    ) -> Result<mpsc::Sender<RecvMsg>, ConfigError>;
+
+    /// 应用层的一条消息
+    fn message(msg: Message) -> Self::SenderMsg;
+
+    /// 关闭连接，完成时通知 `notify`
+    fn close(notify: oneshot::Sender<()>) -> Self::SenderMsg;
+
+    fn timeout(event: Self::Timeout) -> Self::SenderMsg;
+
+    /// 对端回复给本端发送端的 packet，其余 packet 返回 `None` 交给接收端
+    fn feedback(packet: &PacketRef<'_>) -> Option<Self::SenderMsg>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PeerError {
+    #[error(transparent)]
+    GoBackN(#[from] GbnError),
+    #[error(transparent)]
+    SelectResend(#[from] SrError),
+    #[error(transparent)]
+    StopAndWait(#[from] SawError),
+}
+
+/// 按照 `mode` 选择算法运行 [`run_transport`]
+pub async fn run_peer(
+    mode: ArqMode,
+    socket: Arc<UdpSocket>,
+    target: SocketAddr,
+    config: TransportConfig,
+    input: mpsc::Receiver<Vec<u8>>,
+    output: mpsc::Sender<Vec<u8>>,
+) -> Result<(), PeerError> {
+    match mode {
+        ArqMode::GoBackN => run_transport::<GoBackN>(socket, target, config, input, output).await?,
+        ArqMode::SelectResend => {
+            run_transport::<SelectResend>(socket, target, config, input, output).await?
+        }
+        ArqMode::StopAndWait => {
+            run_transport::<StopAndWait>(socket, target, config, input, output).await?
+        }
+    }
+    Ok(())
+}
+
+/// 向 `target` 发送 `input` 中的消息，同时接收任意对端的消息写入 `output`
+///
+/// 发送队列已满时暂停读取 `input`，`input` 结束后关闭连接，关闭完成时返回
+pub async fn run_transport<T: Transport>(
+    socket: Arc<UdpSocket>,
+    target: SocketAddr,
+    config: TransportConfig,
+    mut input: mpsc::Receiver<Vec<u8>>,
+    output: mpsc::Sender<Vec<u8>>,
+) -> Result<(), T::Error> {
+    config.validate(T::MODE)?;
+    let (timeout_send, mut timeouts) = mpsc::channel(16);
+    let (fatal_send, mut fatal) = oneshot::channel();
+    let sender = T::start_send_peer(
+        Arc::clone(&socket),
+        target,
+        timeout_send,
+        config,
+        fatal_send,
+    )?;
+
+    let mut receivers = BTreeMap::new();
+    let receiver = T::start_receive_peer(Arc::clone(&socket), target, output.clone(), config)?;
+    receivers.insert(target, receiver);
+
+    // 发送队列的名额，消息进入窗口后归还
+    let queue = Arc::new(Semaphore::new(config.max_pending));
+    let mut permit = None::<OwnedSemaphorePermit>;
+    // 输入结束后等待关闭完成
+    let mut closed = None::<oneshot::Receiver<()>>;
+    let mut sender_done = false;
+    let mut buf = vec![0u8; MAX_BUFF_SIZE];
+    loop {
+        tokio::select! {
+            recv = socket.recv_from(&mut buf) => {
+                let (size, origin) = recv?;
+                let packet = PacketRef::read(&buf[0..size]);
+                // 队列已满时丢弃 packet，由重传恢复，避免阻塞其他对端
+                if origin == target {
+                    if let Some(msg) = packet.as_ref().ok().and_then(T::feedback) {
+                        sender.try_send(msg).ok();
+                        continue;
+                    }
+                }
+
+                let packet = RecvMsg(packet.map(|packet| packet.to_owned()));
+                // 移除已关闭的接收端
+                receivers.retain(|_, receiver| !receiver.is_closed());
+                match receivers.get(&origin) {
+                    Some(receiver) => {
+                        receiver.try_send(packet).ok();
+                    }
+                    None => {
+                        let receiver = T::start_receive_peer(
+                            Arc::clone(&socket),
+                            origin,
+                            output.clone(),
+                            config,
+                        )?;
+                        receiver.try_send(packet).ok();
+                        receivers.insert(origin, receiver);
+                    }
+                }
+            }
+            Some(event) = timeouts.recv() => {
+                sender.send(T::timeout(event)).await.ok();
+            }
+            acquired = Arc::clone(&queue).acquire_owned(), if closed.is_none() && permit.is_none() => {
+                permit = acquired.ok();
+            }
+            body = input.recv(), if closed.is_none() && permit.is_some() => match body {
+                Some(body) => {
+                    let permit = permit.take().expect("permit acquired");
+                    let msg = Message::new(body).with_permit(permit);
+                    sender.send(T::message(msg)).await.ok();
+                }
+                None => {
+                    let (notify, wait) = oneshot::channel();
+                    sender.send(T::close(notify)).await.ok();
+                    closed = Some(wait);
+                }
+            },
+            _ = async { closed.as_mut().unwrap().await }, if closed.is_some() => {
+                return Ok(());
+            }
+            err = &mut fatal, if !sender_done => match err {
+                Ok(err) => return Err(err),
+                // 发送端正常结束，等待关闭通知
+                Err(_) => sender_done = true,
+            },
+        }
+    }
+}
@@ -1,6 +1,56 @@
 //! verify code of transform body
+//!
+//! 默认使用 16 bit 反码求和（Internet checksum），
+//! 也可以按连接选择 CRC32C 或 64 bit 哈希以发现更多类型的错误
+
+use byteorder::{ByteOrder, ReadBytesExt, BE};
+
+/// 校验算法，写入 packet flag 中
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumKind {
+    /// 16 bit 反码求和
+    #[default]
+    Internet,
+    /// CRC-32C (Castagnoli)
+    Crc32c,
+    /// 64 bit FNV-1a
+    Hash64,
+}
 
-use byteorder::{ReadBytesExt, BE};
+impl ChecksumKind {
+    /// 校验码在 packet 尾部占用的 byte 数
+    pub fn size(&self) -> usize {
+        match self {
+            ChecksumKind::Internet => 2,
+            ChecksumKind::Crc32c => 4,
+            ChecksumKind::Hash64 => 8,
+        }
+    }
+
+    /// 生成 `buf` 的校验码
+    pub fn generate(&self, buf: &[u8]) -> u64 {
+        match self {
+            ChecksumKind::Internet => verify_info_gen(buf) as u64,
+            ChecksumKind::Crc32c => crc32c(buf) as u64,
+            ChecksumKind::Hash64 => hash64(buf),
+        }
+    }
+
+    /// 校验尾部附带校验码的数据
+    pub fn check(&self, data: &[u8]) -> bool {
+        let size = self.size();
+        if data.len() < size {
+            return false;
+        }
+        match self {
+            ChecksumKind::Internet => verify(data),
+            _ => {
+                let (body, code) = data.split_at(data.len() - size);
+                self.generate(body) == BE::read_uint(code, size)
+            }
+        }
+    }
+}
 
 pub fn verify_info_gen(buf: &[u8]) -> u16 {
     let mut start = 0u16;
@@ -47,12 +97,44 @@ pub fn verify(data: &[u8]) -> bool {
     verify == u16::MAX
 }
 
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x82F6_3B78
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc32c(buf: &[u8]) -> u32 {
+    !buf.iter().fold(!0u32, |crc, &b| {
+        CRC32C_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+pub fn hash64(buf: &[u8]) -> u64 {
+    buf.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
 #[cfg(test)]
 mod test {
     use byteorder::{WriteBytesExt, BE};
     use rand::RngCore;
 
-    use super::{verify, verify_info_gen};
+    use super::{crc32c, verify, verify_info_gen, ChecksumKind};
 
     #[test]
     fn test_gen_verify() {
@@ -98,4 +180,30 @@ mod test {
 
         assert!(!verify(&data2))
     }
+
+    #[test]
+    fn test_crc32c() {
+        // check value of CRC-32C
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+        assert_eq!(crc32c(b""), 0);
+    }
+
+    #[test]
+    fn test_checksum_kind() {
+        for kind in [
+            ChecksumKind::Internet,
+            ChecksumKind::Crc32c,
+            ChecksumKind::Hash64,
+        ] {
+            let mut data = b"12345678".to_vec();
+            let code = kind.generate(&data);
+            data.write_uint::<BE>(code, kind.size()).unwrap();
+            assert!(kind.check(&data));
+
+            // swap two 16 bit word, sum cannot find it
+            data.swap(0, 2);
+            data.swap(1, 3);
+            assert_eq!(kind.check(&data), kind == ChecksumKind::Internet);
+        }
+    }
 }